@@ -0,0 +1,214 @@
+use crate::cipher;
+use crate::error;
+
+use cipher::BlockCipher;
+use error::CryptoError;
+
+/// Initial key identifier and 32 bit transaction counter
+pub const KSN_LEN: usize = 12;
+
+/// Maximum number of one bits in a valid transaction counter
+const MAX_COUNTER_BITS: u32 = 16;
+
+/// Type of a derived key
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyType {
+    Tdes2,
+    Tdes3,
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl KeyType {
+    fn algorithm(self) -> u16 {
+        match self {
+            KeyType::Tdes2 => 0x0000,
+            KeyType::Tdes3 => 0x0001,
+            KeyType::Aes128 => 0x0002,
+            KeyType::Aes192 => 0x0003,
+            KeyType::Aes256 => 0x0004,
+        }
+    }
+
+    pub fn key_len(self) -> usize {
+        match self {
+            KeyType::Tdes2 | KeyType::Aes128 => 16,
+            KeyType::Tdes3 | KeyType::Aes192 => 24,
+            KeyType::Aes256 => 32,
+        }
+    }
+
+    /// AES key type of a derivation key
+    pub fn from_key_len(len: usize) -> Result<Self, CryptoError> {
+        match len {
+            16 => Ok(KeyType::Aes128),
+            24 => Ok(KeyType::Aes192),
+            32 => Ok(KeyType::Aes256),
+            len => Err(CryptoError::InvalidKeyLength(len)),
+        }
+    }
+}
+
+/// Purpose of a working key
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyUsage {
+    KeyEncryption,
+    PinEncryption,
+    MacGeneration,
+    MacVerification,
+    MacBoth,
+    DataEncrypt,
+    DataDecrypt,
+    DataBoth,
+}
+
+impl KeyUsage {
+    fn code(self) -> u16 {
+        match self {
+            KeyUsage::KeyEncryption => 0x0002,
+            KeyUsage::PinEncryption => 0x1000,
+            KeyUsage::MacGeneration => 0x2000,
+            KeyUsage::MacVerification => 0x2001,
+            KeyUsage::MacBoth => 0x2002,
+            KeyUsage::DataEncrypt => 0x3000,
+            KeyUsage::DataDecrypt => 0x3001,
+            KeyUsage::DataBoth => 0x3002,
+        }
+    }
+}
+
+const KEY_DERIVATION: u16 = 0x8000;
+const INITIAL_KEY: u16 = 0x8001;
+
+fn derivation_data(usage: u16, key_type: KeyType, id: &[u8], counter: u32) -> [u8; 16] {
+    let mut data = [0; 16];
+    data[0] = 0x01;
+    data[1] = 0x01;
+    data[2..4].copy_from_slice(&usage.to_be_bytes());
+    data[4..6].copy_from_slice(&key_type.algorithm().to_be_bytes());
+    data[6..8].copy_from_slice(&((key_type.key_len() * 8) as u16).to_be_bytes());
+    if usage == INITIAL_KEY {
+        data[8..16].copy_from_slice(&id[..8]);
+    } else {
+        data[8..12].copy_from_slice(&id[4..8]);
+        data[12..16].copy_from_slice(&counter.to_be_bytes());
+    }
+    data
+}
+
+fn derive(key: &[u8], key_type: KeyType, mut data: [u8; 16]) -> Result<Vec<u8>, CryptoError> {
+    let cipher = BlockCipher::aes(key)?;
+    let mut out = vec![];
+    let mut block_counter = 1;
+    while out.len() < key_type.key_len() {
+        data[1] = block_counter;
+        out.extend(cipher.ecb_encrypt(&data)?);
+        block_counter += 1;
+    }
+    out.truncate(key_type.key_len());
+    Ok(out)
+}
+
+fn split_ksn(ksn: &[u8]) -> Result<(&[u8], u32), CryptoError> {
+    if ksn.len() != KSN_LEN {
+        return Err(CryptoError::InvalidKsn(format!(
+            "{} bytes, expected {}",
+            ksn.len(),
+            KSN_LEN
+        )));
+    }
+    let mut counter = [0; 4];
+    counter.copy_from_slice(&ksn[8..]);
+    Ok((&ksn[..8], u32::from_be_bytes(counter)))
+}
+
+/// Initial key of the reader with Initial Key ID, the leftmost 8 bytes of its KSN
+pub fn derive_initial_key(bdk: &[u8], initial_key_id: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if initial_key_id.len() != 8 {
+        return Err(CryptoError::InvalidKsn(format!(
+            "initial key ID of {} bytes",
+            initial_key_id.len()
+        )));
+    }
+    let key_type = KeyType::from_key_len(bdk.len())?;
+    derive(
+        bdk,
+        key_type,
+        derivation_data(INITIAL_KEY, key_type, initial_key_id, 0),
+    )
+}
+
+/// Working key of `working_key_type` for the transaction counter of `ksn`
+pub fn derive_working_key(
+    initial_key: &[u8],
+    ksn: &[u8],
+    usage: KeyUsage,
+    working_key_type: KeyType,
+) -> Result<Vec<u8>, CryptoError> {
+    let (id, counter) = split_ksn(ksn)?;
+    if counter == 0 || counter.count_ones() > MAX_COUNTER_BITS {
+        return Err(CryptoError::InvalidKsn(format!(
+            "transaction counter {:08X}",
+            counter
+        )));
+    }
+
+    let key_type = KeyType::from_key_len(initial_key.len())?;
+    let mut key = initial_key.to_vec();
+    let mut working_counter = 0;
+    for shift in (0..32).rev() {
+        let bit = 1 << shift;
+        if counter & bit != 0 {
+            working_counter |= bit;
+            key = derive(
+                &key,
+                key_type,
+                derivation_data(KEY_DERIVATION, key_type, id, working_counter),
+            )?;
+        }
+    }
+
+    derive(
+        &key,
+        working_key_type,
+        derivation_data(usage.code(), working_key_type, id, counter),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn aes_dukpt_test() {
+        // ANSI X9.24-3-2017, Annex A, AES-128 BDK
+        let bdk = hex("FEDCBA9876543210F1F1F1F1F1F1F1F1");
+        let ksn = hex("123456789012345600000001");
+
+        let initial_key = derive_initial_key(&bdk, &ksn[..8]).unwrap();
+        assert_eq!(initial_key, hex("1273671EA26AC29AFA4D1084127652A1"));
+
+        assert_eq!(
+            derive_working_key(&initial_key, &ksn, KeyUsage::PinEncryption, KeyType::Aes128)
+                .unwrap(),
+            hex("AF8CB133A78F8DC2D1359F18527593FB")
+        );
+
+        assert!(derive_working_key(
+            &initial_key,
+            &hex("12345678901234560000FFFF")[..11],
+            KeyUsage::PinEncryption,
+            KeyType::Aes128
+        )
+        .is_err());
+        assert!(derive_working_key(
+            &initial_key,
+            &hex("1234567890123456FFFF0001"),
+            KeyUsage::PinEncryption,
+            KeyType::Aes128
+        )
+        .is_err());
+    }
+}
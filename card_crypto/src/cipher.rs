@@ -0,0 +1,181 @@
+use crate::error;
+
+use aes::cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit};
+use aes::{Aes128, Aes192, Aes256};
+use des::{Des, TdesEde2, TdesEde3};
+
+use error::CryptoError;
+
+/// Block cipher with a key, used in ECB and CBC modes
+pub(crate) enum BlockCipher {
+    Des(Des),
+    Tdes2(TdesEde2),
+    Tdes3(TdesEde3),
+    Aes128(Aes128),
+    Aes192(Aes192),
+    Aes256(Aes256),
+}
+
+macro_rules! with_cipher {
+    ($self:expr, $cipher:ident => $body:expr) => {
+        match $self {
+            BlockCipher::Des($cipher) => $body,
+            BlockCipher::Tdes2($cipher) => $body,
+            BlockCipher::Tdes3($cipher) => $body,
+            BlockCipher::Aes128($cipher) => $body,
+            BlockCipher::Aes192($cipher) => $body,
+            BlockCipher::Aes256($cipher) => $body,
+        }
+    };
+}
+
+impl BlockCipher {
+    /// Single, double or triple length DES key
+    pub fn tdes(key: &[u8]) -> Result<Self, CryptoError> {
+        let invalid = |_| CryptoError::InvalidKeyLength(key.len());
+        Ok(match key.len() {
+            8 => BlockCipher::Des(Des::new_from_slice(key).map_err(invalid)?),
+            16 => BlockCipher::Tdes2(TdesEde2::new_from_slice(key).map_err(invalid)?),
+            24 => BlockCipher::Tdes3(TdesEde3::new_from_slice(key).map_err(invalid)?),
+            len => return Err(CryptoError::InvalidKeyLength(len)),
+        })
+    }
+
+    pub fn aes(key: &[u8]) -> Result<Self, CryptoError> {
+        let invalid = |_| CryptoError::InvalidKeyLength(key.len());
+        Ok(match key.len() {
+            16 => BlockCipher::Aes128(Aes128::new_from_slice(key).map_err(invalid)?),
+            24 => BlockCipher::Aes192(Aes192::new_from_slice(key).map_err(invalid)?),
+            32 => BlockCipher::Aes256(Aes256::new_from_slice(key).map_err(invalid)?),
+            len => return Err(CryptoError::InvalidKeyLength(len)),
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        match self {
+            BlockCipher::Des(_) | BlockCipher::Tdes2(_) | BlockCipher::Tdes3(_) => 8,
+            _ => 16,
+        }
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8]) {
+        with_cipher!(self, x => x.encrypt_block(GenericArray::from_mut_slice(block)))
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8]) {
+        with_cipher!(self, x => x.decrypt_block(GenericArray::from_mut_slice(block)))
+    }
+
+    fn check_len(&self, data: &[u8]) -> Result<(), CryptoError> {
+        match data.len() % self.block_size() {
+            0 => Ok(()),
+            _ => Err(CryptoError::InvalidDataLength {
+                len: data.len(),
+                block_size: self.block_size(),
+            }),
+        }
+    }
+
+    pub fn ecb_encrypt(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.check_len(data)?;
+        let mut out = data.to_vec();
+        for block in out.chunks_mut(self.block_size()) {
+            self.encrypt_block(block);
+        }
+        Ok(out)
+    }
+
+    pub fn cbc_encrypt(&self, iv: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.check_len(data)?;
+        let mut out = data.to_vec();
+        let mut previous = iv.to_vec();
+        for block in out.chunks_mut(self.block_size()) {
+            xor(block, &previous);
+            self.encrypt_block(block);
+            previous.copy_from_slice(block);
+        }
+        Ok(out)
+    }
+
+    pub fn cbc_decrypt(&self, iv: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.check_len(data)?;
+        let mut out = data.to_vec();
+        let mut previous = iv.to_vec();
+        for block in out.chunks_mut(self.block_size()) {
+            let ciphertext = block.to_vec();
+            self.decrypt_block(block);
+            xor(block, &previous);
+            previous = ciphertext;
+        }
+        Ok(out)
+    }
+
+    /// CMAC of NIST SP 800-38B
+    pub fn cmac(&self, data: &[u8]) -> Vec<u8> {
+        let block_size = self.block_size();
+        let double = |x: &[u8]| {
+            let mut out = vec![0; block_size];
+            for i in 0..block_size {
+                out[i] = x[i] << 1 | x.get(i + 1).map_or(0, |x| x >> 7);
+            }
+            if x[0] & 0x80 != 0 {
+                out[block_size - 1] ^= if block_size == 16 { 0x87 } else { 0x1B };
+            }
+            out
+        };
+
+        let mut subkey = vec![0; block_size];
+        self.encrypt_block(&mut subkey);
+        let k1 = double(&subkey);
+        let k2 = double(&k1);
+
+        let complete = !data.is_empty() && data.len().is_multiple_of(block_size);
+        let last_start = match complete {
+            true => data.len() - block_size,
+            false => data.len() - data.len() % block_size,
+        };
+        let mut last = data[last_start..].to_vec();
+        if complete {
+            xor(&mut last, &k1);
+        } else {
+            last.push(0x80);
+            last.resize(block_size, 0x00);
+            xor(&mut last, &k2);
+        }
+
+        let mut mac = vec![0; block_size];
+        for block in data[..last_start].chunks(block_size).chain(Some(&last[..])) {
+            xor(&mut mac, block);
+            self.encrypt_block(&mut mac);
+        }
+        mac
+    }
+}
+
+pub(crate) fn xor(data: &mut [u8], other: &[u8]) {
+    for (x, y) in data.iter_mut().zip(other) {
+        *x ^= y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn cmac_test() {
+        // NIST SP 800-38B, D.1 and D.4
+        let aes = BlockCipher::aes(&hex("2B7E151628AED2A6ABF7158809CF4F3C")).unwrap();
+        assert_eq!(aes.cmac(&[]), hex("BB1D6929E95937287FA37D129B756746"));
+        assert_eq!(
+            aes.cmac(&hex("6BC1BEE22E409F96E93D7E117393172A")),
+            hex("070A16B46B4D4144F79BDD9DD04A287C")
+        );
+
+        let tdes =
+            BlockCipher::tdes(&hex("8AA83BF8CBDA10620BC1BF19FBB6CD58BC313D4A371CA8B5")).unwrap();
+        assert_eq!(tdes.cmac(&[]), hex("B7A688E122FFAF95"));
+        assert_eq!(tdes.cmac(&hex("6BC1BEE22E409F96")), hex("8E8F293136283797"));
+    }
+}
@@ -0,0 +1,308 @@
+use crate::aes_dukpt;
+use crate::cipher;
+use crate::error;
+
+use aes_dukpt::{KeyType, KeyUsage};
+use std::fmt;
+
+use cipher::{xor, BlockCipher};
+use error::CryptoError;
+use zeroize::Zeroize;
+
+/// Key set identifier, device identifier and 21 bit transaction counter
+pub const KSN_LEN: usize = 10;
+
+const COUNTER_MASK: u64 = 0x1F_FFFF;
+/// Maximum number of one bits in a valid transaction counter
+const MAX_COUNTER_BITS: u32 = 10;
+
+const KEY_MASK: [u8; 16] = [
+    0xC0, 0xC0, 0xC0, 0xC0, 0x00, 0x00, 0x00, 0x00, 0xC0, 0xC0, 0xC0, 0xC0, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Variant of a TDES DUKPT transaction key
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyVariant {
+    Pin,
+    MacRequest,
+    MacResponse,
+    DataRequest,
+    DataResponse,
+}
+
+impl KeyVariant {
+    fn mask(self) -> [u8; 16] {
+        let byte = match self {
+            KeyVariant::Pin => 7,
+            KeyVariant::MacRequest => 6,
+            KeyVariant::MacResponse => 4,
+            KeyVariant::DataRequest => 5,
+            KeyVariant::DataResponse => 3,
+        };
+        let mut mask = [0; 16];
+        mask[byte] = 0xFF;
+        mask[byte + 8] = 0xFF;
+        mask
+    }
+}
+
+fn check_ksn(ksn: &[u8]) -> Result<(), CryptoError> {
+    match ksn.len() {
+        KSN_LEN => Ok(()),
+        len => Err(CryptoError::InvalidKsn(format!(
+            "{} bytes, expected {}",
+            len, KSN_LEN
+        ))),
+    }
+}
+
+fn des_encrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    BlockCipher::tdes(key)?.ecb_encrypt(data)
+}
+
+/// Initial PIN encryption key of ANSI X9.24-1
+pub fn derive_ipek(bdk: &[u8; 16], ksn: &[u8]) -> Result<[u8; 16], CryptoError> {
+    check_ksn(ksn)?;
+    let mut data = ksn[..8].to_vec();
+    data[7] &= 0xE0;
+
+    let mut masked_bdk = *bdk;
+    xor(&mut masked_bdk, &KEY_MASK);
+
+    let mut ipek = [0; 16];
+    ipek[..8].copy_from_slice(&des_encrypt(bdk, &data)?);
+    ipek[8..].copy_from_slice(&des_encrypt(&masked_bdk, &data)?);
+    Ok(ipek)
+}
+
+/// Non-reversible key generation process
+fn generate_key(key: &[u8; 16], data: &[u8]) -> Result<[u8; 16], CryptoError> {
+    let half = |key: &[u8]| -> Result<Vec<u8>, CryptoError> {
+        let mut message = data.to_vec();
+        xor(&mut message, &key[8..]);
+        let mut out = des_encrypt(&key[..8], &message)?;
+        xor(&mut out, &key[8..]);
+        Ok(out)
+    };
+
+    let mut masked_key = *key;
+    xor(&mut masked_key, &KEY_MASK);
+
+    let mut out = [0; 16];
+    out[..8].copy_from_slice(&half(&masked_key)?);
+    out[8..].copy_from_slice(&half(key)?);
+    Ok(out)
+}
+
+/// Transaction key for the counter of `ksn`, apply a variant before use
+pub fn derive_key(ipek: &[u8; 16], ksn: &[u8]) -> Result<[u8; 16], CryptoError> {
+    check_ksn(ksn)?;
+    let mut register = [0; 8];
+    register.copy_from_slice(&ksn[2..]);
+    let register = u64::from_be_bytes(register);
+
+    let counter = register & COUNTER_MASK;
+    if counter == 0 || counter.count_ones() > MAX_COUNTER_BITS {
+        return Err(CryptoError::InvalidKsn(format!(
+            "transaction counter {:06X}",
+            counter
+        )));
+    }
+
+    let mut key = *ipek;
+    let mut register = register & !COUNTER_MASK;
+    for shift in (0..21).rev() {
+        let bit = 1 << shift;
+        if counter & bit != 0 {
+            register |= bit;
+            key = generate_key(&key, &register.to_be_bytes())?;
+        }
+    }
+    Ok(key)
+}
+
+pub fn variant_key(key: &[u8; 16], variant: KeyVariant) -> [u8; 16] {
+    let mut out = *key;
+    xor(&mut out, &variant.mask());
+    out
+}
+
+/// Data variant encrypted with itself, as readers encrypt card data
+pub fn data_key(key: &[u8; 16], variant: KeyVariant) -> Result<[u8; 16], CryptoError> {
+    let variant = variant_key(key, variant);
+    let mut out = [0; 16];
+    out.copy_from_slice(&des_encrypt(&variant, &variant)?);
+    Ok(out)
+}
+
+/// Key which derives data encryption keys of readers
+///
+/// Key bytes are left out of `Debug` and zeroed on drop.
+#[derive(Clone, PartialEq, Eq)]
+pub enum DukptKey {
+    /// TDES base derivation key of ANSI X9.24-1
+    TdesBdk([u8; 16]),
+    /// TDES initial key of one reader
+    TdesIpek([u8; 16]),
+    /// AES base derivation key of ANSI X9.24-3
+    AesBdk(Vec<u8>),
+    /// AES initial key of one reader
+    AesInitialKey(Vec<u8>),
+}
+
+impl fmt::Debug for DukptKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DukptKey::TdesBdk(_) => "TdesBdk",
+            DukptKey::TdesIpek(_) => "TdesIpek",
+            DukptKey::AesBdk(_) => "AesBdk",
+            DukptKey::AesInitialKey(_) => "AesInitialKey",
+        };
+        write!(f, "{}(..)", name)
+    }
+}
+
+impl Drop for DukptKey {
+    fn drop(&mut self) {
+        match self {
+            DukptKey::TdesBdk(key) | DukptKey::TdesIpek(key) => key.zeroize(),
+            DukptKey::AesBdk(key) | DukptKey::AesInitialKey(key) => key.zeroize(),
+        }
+    }
+}
+
+impl DukptKey {
+    /// Expected KSN length
+    pub fn ksn_len(&self) -> usize {
+        match self {
+            DukptKey::TdesBdk(_) | DukptKey::TdesIpek(_) => KSN_LEN,
+            DukptKey::AesBdk(_) | DukptKey::AesInitialKey(_) => aes_dukpt::KSN_LEN,
+        }
+    }
+
+    /// Cipher of the data sent by the reader with `ksn`
+    fn data_cipher(&self, ksn: &[u8]) -> Result<BlockCipher, CryptoError> {
+        if ksn.len() != self.ksn_len() {
+            return Err(CryptoError::InvalidKsn(format!(
+                "{} bytes, expected {}",
+                ksn.len(),
+                self.ksn_len()
+            )));
+        }
+
+        match self {
+            DukptKey::TdesBdk(bdk) => {
+                let key = derive_key(&derive_ipek(bdk, ksn)?, ksn)?;
+                BlockCipher::tdes(&data_key(&key, KeyVariant::DataRequest)?)
+            }
+            DukptKey::TdesIpek(ipek) => {
+                let key = derive_key(ipek, ksn)?;
+                BlockCipher::tdes(&data_key(&key, KeyVariant::DataRequest)?)
+            }
+            DukptKey::AesBdk(bdk) => {
+                let initial_key = aes_dukpt::derive_initial_key(bdk, &ksn[..8])?;
+                Self::aes_data_cipher(&initial_key, ksn)
+            }
+            DukptKey::AesInitialKey(initial_key) => Self::aes_data_cipher(initial_key, ksn),
+        }
+    }
+
+    fn aes_data_cipher(initial_key: &[u8], ksn: &[u8]) -> Result<BlockCipher, CryptoError> {
+        let key_type = KeyType::from_key_len(initial_key.len())?;
+        BlockCipher::aes(&aes_dukpt::derive_working_key(
+            initial_key,
+            ksn,
+            KeyUsage::DataEncrypt,
+            key_type,
+        )?)
+    }
+
+    /// Decrypts CBC data with zero IV, padding is kept
+    ///
+    /// No reader driver calls it yet, the Uno8 protocol documents no encrypted data template.
+    pub fn decrypt_data(&self, ksn: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let cipher = self.data_cipher(ksn)?;
+        cipher.cbc_decrypt(&vec![0; cipher.block_size()], data)
+    }
+
+    /// Encrypts like the reader does, `data` is padded with zeros to the block size
+    pub fn encrypt_data(&self, ksn: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let cipher = self.data_cipher(ksn)?;
+        let block_size = cipher.block_size();
+        let mut data = data.to_vec();
+        data.resize(data.len().div_ceil(block_size) * block_size, 0x00);
+        cipher.cbc_encrypt(&vec![0; block_size], &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    fn key16(s: &str) -> [u8; 16] {
+        let mut key = [0; 16];
+        key.copy_from_slice(&hex(s));
+        key
+    }
+
+    #[test]
+    fn tdes_dukpt_test() {
+        // ANSI X9.24-1-2009, Annex A
+        let bdk = key16("0123456789ABCDEFFEDCBA9876543210");
+        let ksn = hex("FFFF9876543210E00001");
+
+        let ipek = derive_ipek(&bdk, &ksn).unwrap();
+        assert_eq!(ipek, key16("6AC292FAA1315B4D858AB3A3D7D5933A"));
+
+        let key = derive_key(&ipek, &ksn).unwrap();
+        assert_eq!(key, key16("042666B49184CFA368DE9628D0397BC9"));
+
+        let data_request = variant_key(&key, KeyVariant::DataRequest);
+        assert_eq!(data_request, key16("042666B4917BCFA368DE9628D0C67BC9"));
+        assert_eq!(
+            data_key(&key, KeyVariant::DataRequest).unwrap(),
+            key16("448D3F076D8304036A55A3D7E0055A78")
+        );
+
+        // PIN 1234 of PAN 4012345678909 in ISO format 0
+        let pin_key = variant_key(&key, KeyVariant::Pin);
+        assert_eq!(
+            des_encrypt(&pin_key, &hex("041274EDCBA9876F")).unwrap(),
+            hex("1B9C1845EB993A7A")
+        );
+
+        let key = derive_key(&ipek, &hex("FFFF9876543210E00002")).unwrap();
+        assert_eq!(key, key16("C46551CEF9FD24B0AA9AD834130D3BC7"));
+
+        assert!(derive_key(&ipek, &hex("FFFF9876543210E00000")).is_err());
+        assert!(derive_key(&ipek, &hex("FFFF9876543210FFFFFF")).is_err());
+    }
+
+    #[test]
+    fn dukpt_key_test() {
+        let data = b"4761739001010119=22122011143804400000".to_vec();
+
+        let ksn = hex("FFFF9876543210E00012");
+        let bdk = DukptKey::TdesBdk(key16("0123456789ABCDEFFEDCBA9876543210"));
+        let ipek = DukptKey::TdesIpek(key16("6AC292FAA1315B4D858AB3A3D7D5933A"));
+        let encrypted = bdk.encrypt_data(&ksn, &data).unwrap();
+        assert_eq!(encrypted.len(), 40);
+        assert_eq!(
+            ipek.decrypt_data(&ksn, &encrypted).unwrap()[..data.len()],
+            data[..]
+        );
+
+        let ksn = hex("123456789012345600000012");
+        let bdk = DukptKey::AesBdk(hex("FEDCBA9876543210F1F1F1F1F1F1F1F1"));
+        let encrypted = bdk.encrypt_data(&ksn, &data).unwrap();
+        assert_eq!(encrypted.len(), 48);
+        assert_eq!(
+            bdk.decrypt_data(&ksn, &encrypted).unwrap()[..data.len()],
+            data[..]
+        );
+        assert!(bdk.decrypt_data(&ksn[..10], &encrypted).is_err());
+
+        assert_eq!(format!("{:?}", bdk), "AesBdk(..)");
+    }
+}
@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    #[error("invalid key length {0}")]
+    InvalidKeyLength(usize),
+    #[error("data length {len} is not a multiple of block size {block_size}")]
+    InvalidDataLength { len: usize, block_size: usize },
+    #[error("invalid KSN: {0}")]
+    InvalidKsn(String),
+    #[error("invalid key block: {0}")]
+    InvalidKeyBlock(String),
+    #[error("key block MAC mismatch")]
+    MacMismatch,
+    #[error("random generator: {0}")]
+    Random(String),
+}
@@ -0,0 +1,15 @@
+pub mod aes_dukpt;
+pub mod dukpt;
+pub mod tr31;
+
+pub mod error;
+
+mod cipher;
+
+#[cfg(test)]
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
@@ -0,0 +1,540 @@
+use crate::cipher;
+use crate::dukpt;
+use crate::error;
+
+use std::fmt;
+
+use cipher::BlockCipher;
+use dukpt::DukptKey;
+use error::CryptoError;
+use zeroize::{Zeroize, Zeroizing};
+
+const HEADER_LEN: usize = 16;
+/// Pads the header to the cipher block size
+const PADDING_BLOCK: &str = "PB";
+
+/// Key block version with the key derivation binding method
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Version {
+    /// TDES key block protection key
+    B,
+    /// AES key block protection key
+    D,
+}
+
+impl Version {
+    fn id(self) -> char {
+        match self {
+            Version::B => 'B',
+            Version::D => 'D',
+        }
+    }
+
+    fn block_size(self) -> usize {
+        match self {
+            Version::B => 8,
+            Version::D => 16,
+        }
+    }
+
+    fn cipher(self, key: &[u8]) -> Result<BlockCipher, CryptoError> {
+        match self {
+            Version::B => BlockCipher::tdes(key),
+            Version::D => BlockCipher::aes(key),
+        }
+    }
+
+    /// Key block encryption and MAC keys derived from the protection key
+    fn derive_keys(self, kbpk: &[u8]) -> Result<(Vec<u8>, Vec<u8>), CryptoError> {
+        let algorithm: u16 = match (self, kbpk.len()) {
+            (Version::B, 16) => 0x0000,
+            (Version::B, 24) => 0x0001,
+            (Version::D, 16) => 0x0002,
+            (Version::D, 24) => 0x0003,
+            (Version::D, 32) => 0x0004,
+            (_, len) => return Err(CryptoError::InvalidKeyLength(len)),
+        };
+        let cipher = self.cipher(kbpk)?;
+        let bits = (kbpk.len() * 8) as u16;
+
+        let derive = |usage: u16| {
+            let mut key = vec![];
+            let mut counter = 1;
+            while key.len() < kbpk.len() {
+                let mut data = vec![counter];
+                data.extend_from_slice(&usage.to_be_bytes());
+                data.push(0x00);
+                data.extend_from_slice(&algorithm.to_be_bytes());
+                data.extend_from_slice(&bits.to_be_bytes());
+                key.extend(cipher.cmac(&data));
+                counter += 1;
+            }
+            key.truncate(kbpk.len());
+            key
+        };
+        Ok((derive(0x0000), derive(0x0001)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionalBlock {
+    pub id: String,
+    pub data: String,
+}
+
+/// Key block header, fields are printable ASCII
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: Version,
+    /// E.g. `B0` BDK, `B1` DUKPT initial key, `D0` data encryption, `K0` key encryption
+    pub key_usage: String,
+    /// `A` AES, `T` TDES, `D` DES, `H` HMAC
+    pub algorithm: char,
+    /// E.g. `B` both, `E` encrypt, `D` decrypt, `X` key derivation, `N` no restrictions
+    pub mode_of_use: char,
+    pub key_version: String,
+    /// `E` exportable, `N` non-exportable, `S` sensitive
+    pub exportability: char,
+    /// Without the padding block
+    pub optional_blocks: Vec<OptionalBlock>,
+}
+
+impl Header {
+    pub fn new(version: Version, key_usage: &str, algorithm: char, mode_of_use: char) -> Self {
+        Self {
+            version,
+            key_usage: key_usage.into(),
+            algorithm,
+            mode_of_use,
+            key_version: "00".into(),
+            exportability: 'N',
+            optional_blocks: vec![],
+        }
+    }
+
+    pub fn with_key_version(mut self, key_version: &str) -> Self {
+        self.key_version = key_version.into();
+        self
+    }
+
+    pub fn with_exportability(mut self, exportability: char) -> Self {
+        self.exportability = exportability;
+        self
+    }
+
+    pub fn with_optional_block(mut self, id: &str, data: &str) -> Self {
+        self.optional_blocks.push(OptionalBlock {
+            id: id.into(),
+            data: data.into(),
+        });
+        self
+    }
+
+    pub fn optional_block(&self, id: &str) -> Option<&str> {
+        self.optional_blocks
+            .iter()
+            .find(|x| x.id == id)
+            .map(|x| x.data.as_str())
+    }
+
+    /// Parses the header of a key block without checking the key, returns its length
+    pub fn parse(block: &str) -> Result<(Self, usize), CryptoError> {
+        if !block.is_ascii() || block.len() < HEADER_LEN {
+            return Err(invalid("expected ASCII key block with 16 byte header"));
+        }
+
+        let version = match &block[0..1] {
+            "B" => Version::B,
+            "D" => Version::D,
+            version => return Err(invalid(&format!("unsupported version {}", version))),
+        };
+        let declared_len = decimal(&block[1..5])?;
+        if declared_len != block.len() {
+            return Err(invalid(&format!(
+                "length {}, header declares {}",
+                block.len(),
+                declared_len
+            )));
+        }
+
+        let char_at = |i: usize| block.as_bytes()[i] as char;
+        let mut header = Header {
+            version,
+            key_usage: block[5..7].into(),
+            algorithm: char_at(7),
+            mode_of_use: char_at(8),
+            key_version: block[9..11].into(),
+            exportability: char_at(11),
+            optional_blocks: vec![],
+        };
+
+        let mut offset = HEADER_LEN;
+        for _ in 0..decimal(&block[12..14])? {
+            let field = |start: usize, len: usize| {
+                block
+                    .get(start..start + len)
+                    .ok_or_else(|| invalid("truncated optional block"))
+            };
+
+            let id = field(offset, 2)?;
+            let (len, data_offset) = match hexadecimal(field(offset + 2, 2)?)? {
+                0 => {
+                    let len_len = hexadecimal(field(offset + 4, 2)?)?;
+                    (
+                        hexadecimal(field(offset + 6, len_len)?)?,
+                        offset + 6 + len_len,
+                    )
+                }
+                len => (len, offset + 4),
+            };
+            if len < data_offset - offset {
+                return Err(invalid(&format!("optional block {} length {}", id, len)));
+            }
+
+            let data = field(data_offset, offset + len - data_offset)?;
+            if id != PADDING_BLOCK {
+                header.optional_blocks.push(OptionalBlock {
+                    id: id.into(),
+                    data: data.into(),
+                });
+            }
+            offset += len;
+        }
+
+        if !offset.is_multiple_of(version.block_size()) {
+            return Err(invalid(&format!(
+                "header length {} is not a multiple of {}",
+                offset,
+                version.block_size()
+            )));
+        }
+        Ok((header, offset))
+    }
+
+    /// Header with padding block and a placeholder of key block length
+    fn encode(&self) -> Result<String, CryptoError> {
+        let printable = |x: &str, len: Option<usize>| {
+            x.bytes().all(|x| (0x20..0x7F).contains(&x)) && len.is_none_or(|len| x.len() == len)
+        };
+        let fields = format!(
+            "{}{}{}{}",
+            self.algorithm, self.mode_of_use, self.key_version, self.exportability
+        );
+        if !printable(&self.key_usage, Some(2)) || !printable(&fields, Some(5)) {
+            return Err(invalid(
+                "header fields must be printable ASCII of fixed length",
+            ));
+        }
+
+        let mut blocks = String::new();
+        for block in &self.optional_blocks {
+            if !printable(&block.id, Some(2)) || !printable(&block.data, None) {
+                return Err(invalid(&format!("optional block {}", block.id)));
+            }
+            blocks.push_str(&block.id);
+            match block.data.len() + 4 {
+                len if len <= 0xFF => blocks.push_str(&format!("{:02X}", len)),
+                len if len + 6 <= 0xFFFF => blocks.push_str(&format!("0004{:04X}", len + 6)),
+                len => return Err(invalid(&format!("optional block length {}", len))),
+            }
+            blocks.push_str(&block.data);
+        }
+
+        let mut count = self.optional_blocks.len();
+        let block_size = self.version.block_size();
+        if !(HEADER_LEN + blocks.len()).is_multiple_of(block_size) {
+            let mut len = 4;
+            while !(HEADER_LEN + blocks.len() + len).is_multiple_of(block_size) {
+                len += 1;
+            }
+            blocks.push_str(&format!(
+                "{}{:02X}{}",
+                PADDING_BLOCK,
+                len,
+                "0".repeat(len - 4)
+            ));
+            count += 1;
+        }
+        if count > 99 {
+            return Err(invalid(&format!("{} optional blocks", count)));
+        }
+
+        Ok(format!(
+            "{}0000{}{}{:02}00{}",
+            self.version.id(),
+            self.key_usage,
+            fields,
+            count,
+            blocks
+        ))
+    }
+}
+
+/// Key with its header, as exchanged in TR-31 key blocks
+///
+/// The key is left out of `Debug` and zeroed on drop.
+#[derive(Clone, PartialEq, Eq)]
+pub struct KeyBlock {
+    pub header: Header,
+    pub key: Vec<u8>,
+}
+
+impl fmt::Debug for KeyBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyBlock")
+            .field("header", &self.header)
+            .field("key", &format_args!("{} bytes", self.key.len()))
+            .finish()
+    }
+}
+
+impl Drop for KeyBlock {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl KeyBlock {
+    pub fn new(header: Header, key: Vec<u8>) -> Self {
+        Self { header, key }
+    }
+
+    /// Verifies the MAC and decrypts the key with the key block protection key
+    pub fn unwrap(kbpk: &[u8], block: &str) -> Result<Self, CryptoError> {
+        let (header, header_len) = Header::parse(block)?;
+        let version = header.version;
+        let (encryption_key, mac_key) = version.derive_keys(kbpk)?;
+
+        let mac_len = version.block_size() * 2;
+        if block.len() < header_len + mac_len {
+            return Err(invalid("missing key data"));
+        }
+        let encrypted = from_hex(&block[header_len..block.len() - mac_len])?;
+        let mac = from_hex(&block[block.len() - mac_len..])?;
+        if encrypted.is_empty() || !encrypted.len().is_multiple_of(version.block_size()) {
+            return Err(invalid(&format!("key data of {} bytes", encrypted.len())));
+        }
+
+        let key_data = Zeroizing::new(
+            version
+                .cipher(&encryption_key)?
+                .cbc_decrypt(&mac, &encrypted)?,
+        );
+        let mut mac_data = Zeroizing::new(block.as_bytes()[..header_len].to_vec());
+        mac_data.extend_from_slice(&key_data);
+        let expected_mac = version.cipher(&mac_key)?.cmac(&mac_data);
+        if expected_mac
+            .iter()
+            .zip(&mac)
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            != 0
+        {
+            return Err(CryptoError::MacMismatch);
+        }
+
+        let bits = u16::from_be_bytes([key_data[0], key_data[1]]) as usize;
+        if !bits.is_multiple_of(8) || 2 + bits / 8 > key_data.len() {
+            return Err(invalid(&format!("key length of {} bits", bits)));
+        }
+
+        Ok(Self {
+            header,
+            key: key_data[2..2 + bits / 8].to_vec(),
+        })
+    }
+
+    /// Encrypts the key with random padding and appends the MAC
+    pub fn wrap(&self, kbpk: &[u8]) -> Result<String, CryptoError> {
+        let mut padding = vec![0; self.padding_len()];
+        getrandom::getrandom(&mut padding).map_err(|e| CryptoError::Random(e.to_string()))?;
+        self.wrap_with_padding(kbpk, &padding)
+    }
+
+    /// Like `wrap` with given padding of the key data
+    pub fn wrap_with_padding(&self, kbpk: &[u8], padding: &[u8]) -> Result<String, CryptoError> {
+        if padding.len() != self.padding_len() {
+            return Err(invalid(&format!(
+                "padding of {} bytes, expected {}",
+                padding.len(),
+                self.padding_len()
+            )));
+        }
+        let version = self.header.version;
+        let (encryption_key, mac_key) = version.derive_keys(kbpk)?;
+
+        let mut key_data = Zeroizing::new(((self.key.len() * 8) as u16).to_be_bytes().to_vec());
+        key_data.extend_from_slice(&self.key);
+        key_data.extend_from_slice(padding);
+
+        let mut block = self.header.encode()?;
+        let len = block.len() + key_data.len() * 2 + version.block_size() * 2;
+        if len > 9999 {
+            return Err(invalid(&format!("key block length {}", len)));
+        }
+        block.replace_range(1..5, &format!("{:04}", len));
+
+        let mut mac_data = Zeroizing::new(block.as_bytes().to_vec());
+        mac_data.extend_from_slice(&key_data);
+        let mac = version.cipher(&mac_key)?.cmac(&mac_data);
+        let encrypted = version
+            .cipher(&encryption_key)?
+            .cbc_encrypt(&mac, &key_data)?;
+
+        block.push_str(&to_hex(&encrypted));
+        block.push_str(&to_hex(&mac));
+        Ok(block)
+    }
+
+    /// Pads key data to the cipher block size
+    fn padding_len(&self) -> usize {
+        let block_size = self.header.version.block_size();
+        (block_size - (2 + self.key.len()) % block_size) % block_size
+    }
+
+    /// DUKPT key of a `B0` base derivation key or `B1` initial key block
+    pub fn dukpt_key(&self) -> Result<DukptKey, CryptoError> {
+        let tdes_key = || {
+            let mut key = [0; 16];
+            match self.key.len() {
+                16 => {
+                    key.copy_from_slice(&self.key);
+                    Ok(key)
+                }
+                len => Err(CryptoError::InvalidKeyLength(len)),
+            }
+        };
+
+        match (self.header.key_usage.as_str(), self.header.algorithm) {
+            ("B0", 'T') => Ok(DukptKey::TdesBdk(tdes_key()?)),
+            ("B1", 'T') => Ok(DukptKey::TdesIpek(tdes_key()?)),
+            ("B0", 'A') => Ok(DukptKey::AesBdk(self.key.clone())),
+            ("B1", 'A') => Ok(DukptKey::AesInitialKey(self.key.clone())),
+            (usage, algorithm) => Err(invalid(&format!(
+                "key usage {} with algorithm {} is not a DUKPT key",
+                usage, algorithm
+            ))),
+        }
+    }
+}
+
+fn invalid(message: &str) -> CryptoError {
+    CryptoError::InvalidKeyBlock(message.into())
+}
+
+fn decimal(s: &str) -> Result<usize, CryptoError> {
+    match s.bytes().all(|x| x.is_ascii_digit()) {
+        true => s.parse().map_err(|_| invalid(&format!("number {}", s))),
+        false => Err(invalid(&format!("number {}", s))),
+    }
+}
+
+fn hexadecimal(s: &str) -> Result<usize, CryptoError> {
+    match s.bytes().all(|x| x.is_ascii_hexdigit()) {
+        true => usize::from_str_radix(s, 16).map_err(|_| invalid(&format!("length {}", s))),
+        false => Err(invalid(&format!("length {}", s))),
+    }
+}
+
+fn from_hex(s: &str) -> Result<Vec<u8>, CryptoError> {
+    if !s.len().is_multiple_of(2) {
+        return Err(invalid("odd number of hex digits"));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| hexadecimal(&s[i..i + 2]).map(|x| x as u8))
+        .collect()
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|x| format!("{:02X}", x)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn unwrap_test() {
+        // ANSI X9.143, Annex A examples
+        let block = KeyBlock::unwrap(
+            &hex("DD7515F2BFC17F85CE48F3CA25CB21F6"),
+            "B0080P0TE00E000094B420079CC80BA3461F86FE26EFC4A3B8E4FA4C5F5341176EED7B727B8A248E",
+        )
+        .unwrap();
+        assert_eq!(block.key, hex("3F419E1CB7079442AA37474C2EFBF8B8"));
+        assert_eq!(
+            block.header,
+            Header::new(Version::B, "P0", 'T', 'E').with_exportability('E')
+        );
+
+        let kbpk = hex("88E1AB2A2E3DD38C1FA039A536500CC8A87AB9D62DC92C01058FA79F44657DE6");
+        let wrapped = "D0112P0AE00E0000B82679114F470F540165EDFBF7E250FCEA43F810D215F8D207E2E417C07156A27E8E31DA05F7425509593D03A457DC34";
+        let block = KeyBlock::unwrap(&kbpk, wrapped).unwrap();
+        assert_eq!(block.key, hex("3F419E1CB7079442AA37474C2EFBF8B8"));
+        assert_eq!(block.header.algorithm, 'A');
+
+        let mut tampered = wrapped.to_owned();
+        tampered.replace_range(111..112, "5");
+        assert_eq!(
+            KeyBlock::unwrap(&kbpk, &tampered),
+            Err(CryptoError::MacMismatch)
+        );
+        assert!(KeyBlock::unwrap(&kbpk, &wrapped[..100]).is_err());
+    }
+
+    #[test]
+    fn wrap_test() {
+        let kbpk = hex("DD7515F2BFC17F85CE48F3CA25CB21F6");
+        let header = Header::new(Version::B, "B1", 'T', 'X')
+            .with_key_version("12")
+            .with_optional_block("KS", "FFFF9876543210E00000");
+        let block = KeyBlock::new(header, hex("6AC292FAA1315B4D858AB3A3D7D5933A"));
+
+        let wrapped = block.wrap(&kbpk).unwrap();
+        assert_eq!(&wrapped[..40], "B0104B1TX12N0100KS18FFFF9876543210E00000");
+
+        let unwrapped = KeyBlock::unwrap(&kbpk, &wrapped).unwrap();
+        assert_eq!(unwrapped, block);
+        assert_eq!(
+            unwrapped.header.optional_block("KS"),
+            Some("FFFF9876543210E00000")
+        );
+        assert_eq!(
+            unwrapped.dukpt_key().unwrap(),
+            DukptKey::TdesIpek([
+                0x6A, 0xC2, 0x92, 0xFA, 0xA1, 0x31, 0x5B, 0x4D, 0x85, 0x8A, 0xB3, 0xA3, 0xD7, 0xD5,
+                0x93, 0x3A
+            ])
+        );
+
+        let block = KeyBlock::new(
+            Header::new(Version::D, "D0", 'A', 'B')
+                .with_optional_block("KS", "123456789012345600000000"),
+            hex("3F419E1CB7079442AA37474C2EFBF8B8"),
+        );
+        let wrapped = block
+            .wrap_with_padding(&hex("88E1AB2A2E3DD38C1FA039A536500CC8"), &[0; 14])
+            .unwrap();
+        assert_eq!(
+            &wrapped[..48],
+            "D0144D0AB00N0200KS1C123456789012345600000000PB04"
+        );
+        assert_eq!(
+            KeyBlock::unwrap(&hex("88E1AB2A2E3DD38C1FA039A536500CC8"), &wrapped).unwrap(),
+            block
+        );
+        assert!(block.dukpt_key().is_err());
+        assert!(format!("{:?}", block).ends_with("key: 16 bytes }"));
+
+        let long_block = "A".repeat(300);
+        let block = KeyBlock::new(
+            Header::new(Version::D, "K0", 'A', 'B').with_optional_block("CT", &long_block),
+            hex("3F419E1CB7079442AA37474C2EFBF8B8"),
+        );
+        let kbpk = hex("88E1AB2A2E3DD38C1FA039A536500CC8");
+        let wrapped = block.wrap(&kbpk).unwrap();
+        assert_eq!(&wrapped[16..26], "CT00040136");
+        assert_eq!(KeyBlock::unwrap(&kbpk, &wrapped).unwrap(), block);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::error;
+
+use error::ApduError;
+
+/// Maximum length of command data
+pub const MAX_LC: usize = 65535;
+/// Maximum expected length of response data
+pub const MAX_LE: usize = 65536;
+
+/// ISO/IEC 7816-4 command APDU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandApdu {
+    pub cla: u8,
+    pub ins: u8,
+    pub p1: u8,
+    pub p2: u8,
+    pub data: Vec<u8>,
+    /// Expected length of response data, `None` if no data are expected
+    pub le: Option<usize>,
+}
+
+impl CommandApdu {
+    pub fn new(cla: u8, ins: u8, p1: u8, p2: u8) -> Self {
+        Self {
+            cla,
+            ins,
+            p1,
+            p2,
+            data: vec![],
+            le: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Vec<u8>) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub fn with_le(mut self, le: usize) -> Self {
+        self.le = Some(le);
+        self
+    }
+
+    /// SELECT by DF name, e.g. an application identifier
+    pub fn select(name: &[u8]) -> Self {
+        Self::new(0x00, 0xA4, 0x04, 0x00)
+            .with_data(name.to_vec())
+            .with_le(256)
+    }
+
+    /// Extended length fields are required
+    pub fn is_extended(&self) -> bool {
+        self.data.len() > 255 || matches!(self.le, Some(le) if le > 256)
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, ApduError> {
+        if self.data.len() > MAX_LC {
+            return Err(ApduError::DataTooLong(self.data.len()));
+        }
+        match self.le {
+            Some(le) if le == 0 || le > MAX_LE => return Err(ApduError::InvalidLe(le)),
+            _ => {}
+        }
+
+        let mut bytes = vec![self.cla, self.ins, self.p1, self.p2];
+        let extended = self.is_extended();
+        if extended {
+            bytes.push(0x00);
+        }
+
+        if !self.data.is_empty() {
+            if extended {
+                bytes.extend_from_slice(&(self.data.len() as u16).to_be_bytes());
+            } else {
+                bytes.push(self.data.len() as u8);
+            }
+            bytes.extend_from_slice(&self.data);
+        }
+
+        if let Some(le) = self.le {
+            // The maximum length is encoded as zero
+            if extended {
+                bytes.extend_from_slice(&((le % MAX_LE) as u16).to_be_bytes());
+            } else {
+                bytes.push((le % 256) as u8);
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ApduError> {
+        let malformed = || ApduError::Malformed(bytes.len());
+
+        if bytes.len() < 4 {
+            return Err(malformed());
+        }
+        let mut apdu = Self::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+        let body = &bytes[4..];
+
+        let short_le = |x: u8| if x == 0 { 256 } else { x as usize };
+        let extended_le = |x: &[u8]| match u16::from_be_bytes([x[0], x[1]]) {
+            0 => MAX_LE,
+            le => le as usize,
+        };
+
+        match body {
+            [] => {}
+            [le] => apdu.le = Some(short_le(*le)),
+            [0x00, le @ ..] if le.len() == 2 => apdu.le = Some(extended_le(le)),
+            [0x00, rest @ ..] if rest.len() > 2 => {
+                let lc = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+                let rest = &rest[2..];
+                if lc == 0 || rest.len() < lc {
+                    return Err(malformed());
+                }
+                apdu.data = rest[..lc].to_vec();
+                match &rest[lc..] {
+                    [] => {}
+                    le if le.len() == 2 => apdu.le = Some(extended_le(le)),
+                    _ => return Err(malformed()),
+                }
+            }
+            [lc, rest @ ..] if *lc != 0 => {
+                let lc = *lc as usize;
+                if rest.len() < lc {
+                    return Err(malformed());
+                }
+                apdu.data = rest[..lc].to_vec();
+                match &rest[lc..] {
+                    [] => {}
+                    [le] => apdu.le = Some(short_le(*le)),
+                    _ => return Err(malformed()),
+                }
+            }
+            _ => return Err(malformed()),
+        }
+
+        Ok(apdu)
+    }
+}
+
+/// ISO/IEC 7816-4 response APDU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseApdu {
+    pub data: Vec<u8>,
+    /// Status word SW1-SW2
+    pub sw: u16,
+}
+
+impl ResponseApdu {
+    pub fn new(data: Vec<u8>, sw: u16) -> Self {
+        Self { data, sw }
+    }
+
+    pub fn sw1(&self) -> u8 {
+        (self.sw >> 8) as u8
+    }
+
+    pub fn sw2(&self) -> u8 {
+        self.sw as u8
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.sw == 0x9000
+    }
+
+    /// Number of response bytes still available (SW1 = 61)
+    pub fn bytes_available(&self) -> Option<usize> {
+        match self.sw1() {
+            0x61 => Some(short_len(self.sw2())),
+            _ => None,
+        }
+    }
+
+    /// Exact length the command should be repeated with (SW1 = 6C)
+    pub fn wrong_le(&self) -> Option<usize> {
+        match self.sw1() {
+            0x6C => Some(short_len(self.sw2())),
+            _ => None,
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut bytes = self.data.clone();
+        bytes.extend_from_slice(&self.sw.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ApduError> {
+        match bytes.len() {
+            0 | 1 => Err(ApduError::Malformed(bytes.len())),
+            len => Ok(Self {
+                data: bytes[..len - 2].to_vec(),
+                sw: u16::from_be_bytes([bytes[len - 2], bytes[len - 1]]),
+            }),
+        }
+    }
+}
+
+fn short_len(x: u8) -> usize {
+    if x == 0 {
+        256
+    } else {
+        x as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_apdu_test() {
+        let cases = vec![
+            (CommandApdu::new(0x00, 0xB0, 0x00, 0x00), vec![0x00, 0xB0, 0x00, 0x00]),
+            (
+                CommandApdu::new(0x00, 0xB0, 0x00, 0x00).with_le(256),
+                vec![0x00, 0xB0, 0x00, 0x00, 0x00],
+            ),
+            (
+                CommandApdu::new(0x00, 0xD6, 0x00, 0x00).with_data(vec![1, 2]),
+                vec![0x00, 0xD6, 0x00, 0x00, 0x02, 1, 2],
+            ),
+            (
+                CommandApdu::select(&[0xA0, 0x00]),
+                vec![0x00, 0xA4, 0x04, 0x00, 0x02, 0xA0, 0x00, 0x00],
+            ),
+            (
+                CommandApdu::new(0x00, 0xB0, 0x00, 0x00).with_le(MAX_LE),
+                vec![0x00, 0xB0, 0x00, 0x00, 0x00, 0x00, 0x00],
+            ),
+            (
+                CommandApdu::new(0x00, 0xB0, 0x00, 0x00).with_le(1000),
+                vec![0x00, 0xB0, 0x00, 0x00, 0x00, 0x03, 0xE8],
+            ),
+        ];
+        for (apdu, bytes) in cases {
+            assert_eq!(apdu.to_vec().unwrap(), bytes);
+            assert_eq!(CommandApdu::from_bytes(&bytes).unwrap(), apdu);
+        }
+
+        let extended = CommandApdu::new(0x00, 0xD6, 0x00, 0x00)
+            .with_data(vec![0x55; 300])
+            .with_le(2);
+        let bytes = extended.to_vec().unwrap();
+        assert_eq!(&bytes[4..7], &[0x00, 0x01, 0x2C]);
+        assert_eq!(&bytes[bytes.len() - 2..], &[0x00, 0x02]);
+        assert_eq!(CommandApdu::from_bytes(&bytes).unwrap(), extended);
+
+        assert!(CommandApdu::new(0, 0, 0, 0).with_le(0).to_vec().is_err());
+        assert!(CommandApdu::from_bytes(&[0x00, 0xB0, 0x00]).is_err());
+        assert!(CommandApdu::from_bytes(&[0x00, 0xB0, 0x00, 0x00, 0x03, 1]).is_err());
+    }
+
+    #[test]
+    fn response_apdu_test() {
+        let response = ResponseApdu::from_bytes(&[0x6F, 0x00, 0x90, 0x00]).unwrap();
+        assert_eq!(response.data, vec![0x6F, 0x00]);
+        assert!(response.is_success());
+
+        let response = ResponseApdu::from_bytes(&[0x61, 0x10]).unwrap();
+        assert_eq!(response.bytes_available(), Some(16));
+        assert_eq!(response.to_vec(), vec![0x61, 0x10]);
+
+        assert!(ResponseApdu::from_bytes(&[0x90]).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use crate::device;
+use crate::error;
+use crate::storage;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use device::*;
+use error::*;
+use storage::{Storage, StorageResult};
+
+pub type DeviceFuture<T> = Pin<Box<dyn Future<Output = Result<T, DeviceError>> + Send>>;
+pub type StorageFuture<T> = Pin<Box<dyn Future<Output = StorageResult<T>> + Send>>;
+
+/// Non blocking counterpart of `CardLessDevice`
+///
+/// Only the most used calls have their own method, everything else goes through `run`,
+/// e.g. `device.run(|device| device.device_info())`.
+/// The futures do not depend on a particular runtime.
+pub trait AsyncCardLessDevice {
+    type Device: CardLessDevice;
+
+    /// Runs `job` with the device, calls are executed one by one in the order they are made
+    fn run<T, F>(&self, job: F) -> DeviceFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Self::Device) -> Result<T, DeviceError> + Send + 'static;
+
+    /// Same as `run` for calls taking a `CancellationToken`,
+    /// dropping the future before it completes cancels the call
+    fn run_cancelable<T, F>(&self, cancel: &CancellationToken, job: F) -> DeviceFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Self::Device, &CancellationToken) -> Result<T, DeviceError> + Send + 'static,
+    {
+        let cancel = cancel.child();
+        let cancel_ref = cancel.clone();
+
+        let inner = self.run(move |device| job(device, &cancel_ref));
+
+        Box::pin(CancelOnDrop {
+            inner,
+            cancel,
+            completed: false,
+        })
+    }
+
+    /// Runs `job` with the device storage, `DeviceError::NotSupported` if there is none
+    fn run_storage<T, F>(&self, job: F) -> StorageFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn Storage) -> StorageResult<T> + Send + 'static;
+
+    fn get_sn(&self) -> DeviceFuture<String> {
+        self.run(|device| device.get_sn())
+    }
+
+    /// Dropping the future before it completes cancels the poll
+    fn poll_emv(
+        &self,
+        purchase: Option<PollEmvPurchase>,
+        cancel: &CancellationToken,
+    ) -> DeviceFuture<PollEmvResult> {
+        self.run_cancelable(cancel, move |device, cancel| {
+            device.poll_emv(purchase, cancel)
+        })
+    }
+
+    fn get_display_mode(&self) -> DeviceFuture<ExtDisplayMode> {
+        self.run(|device| match device.ext_display() {
+            Some(display) => display.get_display_mode(),
+            None => Err(DeviceError::NotSupported),
+        })
+    }
+
+    fn set_display_mode(&self, mode: ExtDisplayMode) -> DeviceFuture<()> {
+        self.run(move |device| match device.ext_display() {
+            Some(display) => display.set_display_mode(&mode),
+            None => Err(DeviceError::NotSupported),
+        })
+    }
+}
+
+type Job<D> = Box<dyn FnOnce(&mut D) + Send>;
+
+/// Runs calls of a blocking `CardLessDevice` one by one outside of the async caller
+pub struct AsyncDevice<D> {
+    job_tx: Mutex<Sender<Job<D>>>,
+}
+
+impl<D> AsyncDevice<D>
+where
+    D: CardLessDevice + Send + 'static,
+{
+    /// Moves device to a dedicated thread
+    pub fn new(device: D) -> Self {
+        let (job_tx, worker) = Self::worker(device);
+        thread::spawn(worker);
+
+        Self {
+            job_tx: Mutex::new(job_tx),
+        }
+    }
+
+    /// Moves device to a single `tokio::task::spawn_blocking` task,
+    /// must be used inside a tokio runtime
+    #[cfg(feature = "tokio")]
+    pub fn with_tokio(device: D) -> Self {
+        let (job_tx, worker) = Self::worker(device);
+        tokio::task::spawn_blocking(worker);
+
+        Self {
+            job_tx: Mutex::new(job_tx),
+        }
+    }
+
+    /// Loop running the jobs in the order they are sent, it ends when the sender is dropped
+    fn worker(device: D) -> (Sender<Job<D>>, impl FnOnce() + Send + 'static) {
+        let (job_tx, job_rx) = mpsc::channel::<Job<D>>();
+
+        let worker = move || {
+            let mut device = device;
+            for job in job_rx {
+                job(&mut device);
+            }
+        };
+
+        (job_tx, worker)
+    }
+
+    fn spawn<T, E>(
+        &self,
+        job: impl FnOnce(&mut D) -> Result<T, E> + Send + 'static,
+    ) -> Pin<Box<dyn Future<Output = Result<T, E>> + Send>>
+    where
+        T: Send + 'static,
+        E: From<DeviceError> + Send + 'static,
+    {
+        let (tx, rx) = oneshot();
+
+        // if the worker is gone the dropped job closes the future
+        let job: Job<D> = Box::new(move |device| tx.send(job(device)));
+        let _ = self.job_tx.lock().unwrap().send(job);
+
+        Box::pin(async move {
+            match rx.await {
+                Some(result) => result,
+                None => Err(DeviceError::MessageChannel("device call is aborted".into()).into()),
+            }
+        })
+    }
+}
+
+impl<D> AsyncCardLessDevice for AsyncDevice<D>
+where
+    D: CardLessDevice + Send + 'static,
+{
+    type Device = D;
+
+    fn run<T, F>(&self, job: F) -> DeviceFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut D) -> Result<T, DeviceError> + Send + 'static,
+    {
+        self.spawn(job)
+    }
+
+    fn run_storage<T, F>(&self, job: F) -> StorageFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn Storage) -> StorageResult<T> + Send + 'static,
+    {
+        self.spawn(move |device| match device.storage() {
+            Some(storage) => job(storage),
+            None => Err(DeviceError::NotSupported.into()),
+        })
+    }
+}
+
+/// Cancels the operation when its future is dropped before completion
+struct CancelOnDrop<T> {
+    inner: DeviceFuture<T>,
+    cancel: CancellationToken,
+    completed: bool,
+}
+
+impl<T> Future for CancelOnDrop<T> {
+    type Output = Result<T, DeviceError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let poll = self.inner.as_mut().poll(cx);
+        if poll.is_ready() {
+            self.completed = true;
+        }
+        poll
+    }
+}
+
+impl<T> Drop for CancelOnDrop<T> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.cancel.cancel();
+        }
+    }
+}
+
+struct OneshotState<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+struct OneshotSender<T> {
+    state: Arc<Mutex<OneshotState<T>>>,
+}
+
+struct OneshotReceiver<T> {
+    state: Arc<Mutex<OneshotState<T>>>,
+}
+
+fn oneshot<T>() -> (OneshotSender<T>, OneshotReceiver<T>) {
+    let state = Arc::new(Mutex::new(OneshotState {
+        value: None,
+        waker: None,
+        closed: false,
+    }));
+    (
+        OneshotSender {
+            state: state.clone(),
+        },
+        OneshotReceiver { state },
+    )
+}
+
+impl<T> OneshotSender<T> {
+    fn send(self, value: T) {
+        self.state.lock().unwrap().value = Some(value);
+    }
+}
+
+impl<T> Drop for OneshotSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for OneshotReceiver<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(value) = state.value.take() {
+            return Poll::Ready(Some(value));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::device_info::Capability;
+    use crate::memory_storage::MemoryStorage;
+
+    use std::task::Wake;
+    use std::time::Duration;
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    struct FakeDevice {
+        storage: MemoryStorage,
+        polls: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl CardLessDevice for FakeDevice {
+        fn get_sn(&self) -> Result<String, DeviceError> {
+            Ok("1_2_00000003".into())
+        }
+
+        fn poll_emv(
+            &mut self,
+            _purchase: Option<PollEmvPurchase>,
+            cancel: &CancellationToken,
+        ) -> Result<PollEmvResult, DeviceError> {
+            self.polls.lock().unwrap().push("started");
+            assert!(cancel.wait_timeout(Duration::from_secs(60)));
+            self.polls.lock().unwrap().push("canceled");
+            Ok(PollEmvResult::Canceled)
+        }
+
+        fn ext_display(&mut self) -> Option<&dyn ExtDisplay> {
+            None
+        }
+
+        fn storage(&mut self) -> Option<&dyn Storage> {
+            Some(&self.storage)
+        }
+    }
+
+    #[test]
+    fn async_device_test() {
+        let polls = Arc::new(Mutex::new(vec![]));
+        let device = AsyncDevice::new(FakeDevice {
+            storage: MemoryStorage::new(),
+            polls: polls.clone(),
+        });
+
+        assert_eq!(block_on(device.get_sn()).unwrap(), "1_2_00000003");
+        assert!(matches!(
+            block_on(device.run(|device| device.device_info())),
+            Err(DeviceError::NotSupported)
+        ));
+        let capabilities = block_on(device.run(|device| Ok(device.capabilities()))).unwrap();
+        assert!(capabilities.contains(Capability::Storage));
+        assert!(!capabilities.contains(Capability::ExtDisplay));
+        assert!(matches!(
+            block_on(device.get_display_mode()),
+            Err(DeviceError::NotSupported)
+        ));
+
+        block_on(device.run_storage(|storage| storage.write_file("/a.txt", b"abc"))).unwrap();
+        assert_eq!(
+            block_on(device.run_storage(|storage| storage.read_file("/a.txt"))).unwrap(),
+            b"abc"
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn tokio_order_test() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let _guard = runtime.enter();
+
+        let device = AsyncDevice::with_tokio(FakeDevice {
+            storage: MemoryStorage::new(),
+            polls: Arc::new(Mutex::new(vec![])),
+        });
+
+        let calls = Arc::new(Mutex::new(vec![]));
+        let futures: Vec<_> = (0..10)
+            .map(|i| {
+                let calls = calls.clone();
+                device.run(move |_| {
+                    calls.lock().unwrap().push(i);
+                    Ok(())
+                })
+            })
+            .collect();
+        for future in futures {
+            runtime.block_on(future).unwrap();
+        }
+        assert_eq!(*calls.lock().unwrap(), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_cancels_poll_test() {
+        let polls = Arc::new(Mutex::new(vec![]));
+        let device = AsyncDevice::new(FakeDevice {
+            storage: MemoryStorage::new(),
+            polls: polls.clone(),
+        });
+
+        let mut poll = device.poll_emv(None, &CancellationToken::new());
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        assert!(poll
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+        drop(poll);
+
+        // calls are serialized, so the poll is over when this one completes
+        block_on(device.get_sn()).unwrap();
+        assert_eq!(*polls.lock().unwrap(), vec!["started", "canceled"]);
+    }
+}
@@ -0,0 +1,474 @@
+use crate::error;
+use crate::tlv_parser;
+
+use std::fmt;
+
+use error::*;
+use tlv_parser::{Tlv, Value};
+
+pub const CHECKSUM_SIZE: usize = 20;
+
+/// Registered Application Provider Identifier and CA Public Key Index
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CapkId {
+    pub rid: [u8; 5],
+    pub index: u8,
+}
+
+impl fmt::Display for CapkId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in &self.rid {
+            write!(f, "{:02X}", b)?;
+        }
+        write!(f, "/{:02X}", self.index)
+    }
+}
+
+/// Certification authority public key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capk {
+    pub id: CapkId,
+    pub modulus: Vec<u8>,
+    pub exponent: Vec<u8>,
+    /// YYMMDD in BCD
+    pub expiry: Option<[u8; 3]>,
+    pub checksum: [u8; CHECKSUM_SIZE],
+}
+
+impl Capk {
+    /// Creates the key with computed checksum
+    pub fn new(rid: [u8; 5], index: u8, modulus: Vec<u8>, exponent: Vec<u8>) -> Self {
+        let mut capk = Self {
+            id: CapkId { rid, index },
+            modulus,
+            exponent,
+            expiry: None,
+            checksum: [0; CHECKSUM_SIZE],
+        };
+        capk.checksum = capk.compute_checksum();
+        capk
+    }
+
+    pub fn with_expiry(mut self, expiry: [u8; 3]) -> Self {
+        self.expiry = Some(expiry);
+        self
+    }
+
+    /// Replaces the computed checksum by the published one, see `verify`
+    pub fn with_checksum(mut self, checksum: [u8; CHECKSUM_SIZE]) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// SHA-1 of RID, index, modulus and exponent (EMV Book 2, 11.2.2)
+    pub fn compute_checksum(&self) -> [u8; CHECKSUM_SIZE] {
+        let mut sha1 = sha1_smol::Sha1::new();
+        sha1.update(&self.id.rid);
+        sha1.update(&[self.id.index]);
+        sha1.update(&self.modulus);
+        sha1.update(&self.exponent);
+        sha1.digest().bytes()
+    }
+
+    pub fn verify(&self) -> Result<(), CapkError> {
+        if self.compute_checksum() != self.checksum {
+            return Err(CapkError::ChecksumMismatch(self.id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// `today` is YYMMDD in BCD
+    pub fn is_expired(&self, today: [u8; 3]) -> bool {
+        match self.expiry {
+            Some(expiry) => expiry < today,
+            None => false,
+        }
+    }
+}
+
+/// Key loaded on the reader
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapkEntry {
+    pub id: CapkId,
+    pub checksum: [u8; CHECKSUM_SIZE],
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapkSyncReport {
+    pub added: Vec<CapkId>,
+    pub deleted: Vec<CapkId>,
+    pub unchanged: Vec<CapkId>,
+}
+
+/// CA public keys of the reader
+///
+/// No reader driver implements it yet, the Uno8 protocol documents no command for CA public keys.
+pub trait CapkStore {
+    fn list_capks(&self) -> Result<Vec<CapkEntry>, DeviceError>;
+
+    /// Adds the key or replaces the key with the same id
+    fn add_capk(&self, capk: &Capk) -> Result<(), DeviceError>;
+
+    fn delete_capk(&self, id: &CapkId) -> Result<(), DeviceError>;
+
+    /// Makes the reader have exactly `capks`, keys with the same checksum are not reloaded
+    ///
+    /// Changed keys are replaced by `add_capk`, only keys missing from `capks` are deleted.
+    fn sync_capks(&self, capks: &[Capk]) -> Result<CapkSyncReport, DeviceError> {
+        for capk in capks {
+            capk.verify()?;
+        }
+
+        let loaded = self.list_capks()?;
+        let is_loaded = |capk: &Capk| {
+            loaded
+                .iter()
+                .any(|x| x.id == capk.id && x.checksum == capk.checksum)
+        };
+
+        // Keys are added first, so a failure never leaves the reader with fewer keys
+        let mut report = CapkSyncReport::default();
+        for capk in capks {
+            if is_loaded(capk) {
+                report.unchanged.push(capk.id);
+            } else {
+                self.add_capk(capk)?;
+                report.added.push(capk.id);
+            }
+        }
+        for entry in &loaded {
+            if !capks.iter().any(|x| x.id == entry.id) {
+                self.delete_capk(&entry.id)?;
+                report.deleted.push(entry.id);
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Parses CAPK file detecting its format
+///
+/// Text format has `NAME=value` lines, keys are separated by blank lines:
+///
+/// ```text
+/// # Visa test key
+/// RID=A000000003
+/// INDEX=92
+/// EXPONENT=03
+/// MODULUS=996AF56F...
+/// EXPIRY=291231
+/// CHECKSUM=429C9546...
+/// ```
+///
+/// TLV format has a key per line as hex of data objects 9F06 (RID), 9F22 (index), DF02 (modulus),
+/// DF04 (exponent), DF03 (checksum) and DF05 (expiry as ASCII YYYYMMDD).
+///
+/// Lines starting with `#` or `;` are comments. Keys with checksum are verified.
+pub fn parse_capk_file(content: &str) -> Result<Vec<Capk>, CapkError> {
+    let first_line = content
+        .lines()
+        .map(|x| x.trim())
+        .find(|x| !x.is_empty() && !is_comment(x));
+    let is_text = match first_line {
+        Some(line) => line.contains('='),
+        None => true,
+    };
+
+    if is_text {
+        parse_capk_text(content)
+    } else {
+        parse_capk_tlv(content)
+    }
+}
+
+pub fn parse_capk_text(content: &str) -> Result<Vec<Capk>, CapkError> {
+    let mut capks = vec![];
+    let mut fields = CapkFields::default();
+
+    for (number, line) in content.lines().enumerate() {
+        let number = number + 1;
+        let line = line.trim();
+
+        if line.is_empty() {
+            if !fields.is_empty() {
+                capks.push(fields.finish(number)?);
+                fields = CapkFields::default();
+            }
+            continue;
+        }
+        if is_comment(line) {
+            continue;
+        }
+
+        let (name, value) = match line.find('=') {
+            Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+            None => return Err(malformed(number, "expected NAME=value")),
+        };
+        let bytes = decode_hex(value).ok_or_else(|| malformed(number, "expected hex value"))?;
+
+        match name.to_ascii_uppercase().as_str() {
+            "RID" => fields.rid = Some(bytes),
+            "INDEX" => fields.index = Some(bytes),
+            "MODULUS" => fields.modulus = Some(bytes),
+            "EXPONENT" => fields.exponent = Some(bytes),
+            "EXPIRY" => fields.expiry = Some(bytes),
+            "CHECKSUM" => fields.checksum = Some(bytes),
+            _ => return Err(malformed(number, &format!("unknown field {}", name))),
+        }
+    }
+
+    if !fields.is_empty() {
+        capks.push(fields.finish(content.lines().count())?);
+    }
+    Ok(capks)
+}
+
+pub fn parse_capk_tlv(content: &str) -> Result<Vec<Capk>, CapkError> {
+    let mut capks = vec![];
+
+    for (number, line) in content.lines().enumerate() {
+        let number = number + 1;
+        let line = line.trim();
+        if line.is_empty() || is_comment(line) {
+            continue;
+        }
+
+        let mut data = &decode_hex(line).ok_or_else(|| malformed(number, "expected hex line"))?[..];
+        let mut fields = CapkFields::default();
+        while !data.is_empty() {
+            let tlv = Tlv::from_vec(data).map_err(|e| malformed(number, &format!("{:?}", e)))?;
+            data = &data[tlv.len()..];
+
+            let value = match tlv.val() {
+                Value::Val(value) => value.clone(),
+                _ => vec![],
+            };
+            match tlv.tag() {
+                0x9F06 => fields.rid = Some(value),
+                0x9F22 => fields.index = Some(value),
+                0xDF02 => fields.modulus = Some(value),
+                0xDF04 => fields.exponent = Some(value),
+                0xDF03 => fields.checksum = Some(value),
+                0xDF05 => {
+                    // YYYYMMDD
+                    let expiry = std::str::from_utf8(&value)
+                        .ok()
+                        .filter(|x| x.len() == 8)
+                        .and_then(|x| decode_hex(&x[2..]))
+                        .ok_or_else(|| malformed(number, "expected expiry as YYYYMMDD"))?;
+                    fields.expiry = Some(expiry);
+                }
+                // hash and public key algorithm indicators
+                _ => {}
+            }
+        }
+        capks.push(fields.finish(number)?);
+    }
+    Ok(capks)
+}
+
+#[derive(Default)]
+struct CapkFields {
+    rid: Option<Vec<u8>>,
+    index: Option<Vec<u8>>,
+    modulus: Option<Vec<u8>>,
+    exponent: Option<Vec<u8>>,
+    expiry: Option<Vec<u8>>,
+    checksum: Option<Vec<u8>>,
+}
+
+impl CapkFields {
+    fn is_empty(&self) -> bool {
+        self.rid.is_none()
+            && self.index.is_none()
+            && self.modulus.is_none()
+            && self.exponent.is_none()
+            && self.expiry.is_none()
+            && self.checksum.is_none()
+    }
+
+    fn finish(self, line: usize) -> Result<Capk, CapkError> {
+        let rid = match self.rid {
+            Some(rid) if rid.len() == 5 => [rid[0], rid[1], rid[2], rid[3], rid[4]],
+            _ => return Err(malformed(line, "expected RID of 5 bytes")),
+        };
+        let index = match self.index.as_deref() {
+            Some(&[index]) => index,
+            _ => return Err(malformed(line, "expected index of 1 byte")),
+        };
+        let modulus = match self.modulus {
+            Some(modulus) if !modulus.is_empty() => modulus,
+            _ => return Err(malformed(line, "expected modulus")),
+        };
+        let exponent = match self.exponent {
+            Some(exponent) if !exponent.is_empty() => exponent,
+            _ => return Err(malformed(line, "expected exponent")),
+        };
+
+        let mut capk = Capk::new(rid, index, modulus, exponent);
+        match self.expiry.as_deref() {
+            Some(&[yy, mm, dd]) => capk = capk.with_expiry([yy, mm, dd]),
+            Some(_) => return Err(malformed(line, "expected expiry as YYMMDD")),
+            None => {}
+        }
+        if let Some(checksum) = self.checksum {
+            if checksum.len() != CHECKSUM_SIZE {
+                return Err(malformed(line, "expected checksum of 20 bytes"));
+            }
+            let mut expected = [0; CHECKSUM_SIZE];
+            expected.copy_from_slice(&checksum);
+            capk = capk.with_checksum(expected);
+            capk.verify()?;
+        }
+        Ok(capk)
+    }
+}
+
+fn is_comment(line: &str) -> bool {
+    line.starts_with('#') || line.starts_with(';')
+}
+
+fn malformed(line: usize, message: &str) -> CapkError {
+    CapkError::Malformed {
+        line,
+        message: message.into(),
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits = hex
+        .chars()
+        .filter(|x| !x.is_whitespace())
+        .map(|x| x.to_digit(16).map(|x| x as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    if !digits.chunks_exact(2).remainder().is_empty() {
+        return None;
+    }
+    Some(digits.chunks(2).map(|x| x[0] * 16 + x[1]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|x| format!("{:02X}", x)).collect()
+    }
+
+    fn test_capk(index: u8) -> Capk {
+        Capk::new(
+            [0xA0, 0x00, 0x00, 0x00, 0x03],
+            index,
+            vec![0xC6; 64],
+            vec![0x03],
+        )
+        .with_expiry([0x29, 0x12, 0x31])
+    }
+
+    #[test]
+    fn parse_capk_file_test() {
+        let capk = test_capk(0x92);
+        assert_eq!(capk.id.to_string(), "A000000003/92");
+        assert!(capk.is_expired([0x30, 0x01, 0x01]));
+        assert!(!capk.is_expired([0x29, 0x12, 0x31]));
+
+        let text = format!(
+            "# test key\nRID=A000000003\nINDEX=92\nEXPONENT=03\nMODULUS={}\nEXPIRY=291231\nCHECKSUM={}\n\n\
+             RID = A000000003\nINDEX = 94\nEXPONENT = 03\nMODULUS = {}\n",
+            to_hex(&capk.modulus),
+            to_hex(&capk.checksum),
+            to_hex(&capk.modulus),
+        );
+        let capks = parse_capk_file(&text).unwrap();
+        assert_eq!(
+            capks,
+            vec![
+                capk.clone(),
+                Capk::new(capk.id.rid, 0x94, capk.modulus.clone(), vec![0x03])
+            ]
+        );
+
+        let tlv = format!(
+            "; PBOC\n9F0605A000000003 9F220192 DF05083230323931323331 DF040103 DF0240{} DF0314{}\n",
+            to_hex(&capk.modulus),
+            to_hex(&capk.checksum),
+        );
+        assert_eq!(parse_capk_file(&tlv).unwrap(), vec![capk.clone()]);
+
+        let broken = text.replacen("EXPONENT=03", "EXPONENT=01", 1);
+        assert!(matches!(
+            parse_capk_file(&broken),
+            Err(CapkError::ChecksumMismatch(_))
+        ));
+        assert!(matches!(
+            parse_capk_file("RID=A000000003\nINDEX=92\n"),
+            Err(CapkError::Malformed { line: 2, .. })
+        ));
+    }
+
+    struct FakeStore {
+        loaded: RefCell<Vec<Capk>>,
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl CapkStore for FakeStore {
+        fn list_capks(&self) -> Result<Vec<CapkEntry>, DeviceError> {
+            Ok(self
+                .loaded
+                .borrow()
+                .iter()
+                .map(|x| CapkEntry {
+                    id: x.id,
+                    checksum: x.checksum,
+                })
+                .collect())
+        }
+
+        fn add_capk(&self, capk: &Capk) -> Result<(), DeviceError> {
+            self.calls.borrow_mut().push(format!("add {}", capk.id));
+            self.loaded.borrow_mut().retain(|x| x.id != capk.id);
+            self.loaded.borrow_mut().push(capk.clone());
+            Ok(())
+        }
+
+        fn delete_capk(&self, id: &CapkId) -> Result<(), DeviceError> {
+            self.calls.borrow_mut().push(format!("delete {}", id));
+            self.loaded.borrow_mut().retain(|x| x.id != *id);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sync_capks_test() {
+        let store = FakeStore {
+            loaded: RefCell::new(vec![test_capk(0x01), test_capk(0x02), test_capk(0x05)]),
+            calls: RefCell::new(vec![]),
+        };
+
+        let mut changed = test_capk(0x02);
+        changed.exponent = vec![0x01, 0x00, 0x01];
+        let changed = Capk::new(changed.id.rid, 0x02, changed.modulus, changed.exponent);
+
+        let report = store
+            .sync_capks(&[test_capk(0x01), changed.clone(), test_capk(0x03)])
+            .unwrap();
+        assert_eq!(report.unchanged, vec![test_capk(0x01).id]);
+        assert_eq!(report.deleted, vec![test_capk(0x05).id]);
+        assert_eq!(report.added, vec![changed.id, test_capk(0x03).id]);
+        assert_eq!(store.loaded.borrow().len(), 3);
+        assert_eq!(
+            *store.calls.borrow(),
+            vec!["add A000000003/02", "add A000000003/03", "delete A000000003/05"]
+        );
+
+        let mut invalid = test_capk(0x04);
+        invalid.checksum = [0; CHECKSUM_SIZE];
+        assert!(matches!(
+            store.sync_capks(&[invalid]),
+            Err(DeviceError::Capk(CapkError::ChecksumMismatch(_)))
+        ));
+        assert_eq!(store.loaded.borrow().len(), 3);
+    }
+}
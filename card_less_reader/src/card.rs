@@ -0,0 +1,93 @@
+use crate::apdu;
+use crate::device;
+use crate::error;
+
+use apdu::{CommandApdu, ResponseApdu};
+use device::CancellationToken;
+use error::DeviceError;
+
+/// RF technology of a detected card
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CardTechnology {
+    /// ISO/IEC 14443 Type A
+    IsoA,
+    /// ISO/IEC 14443 Type B
+    IsoB,
+    /// JIS X 6319-4
+    Felica,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CardType {
+    MifareUltralight,
+    MifareMini,
+    MifareClassic1K,
+    MifareClassic4K,
+    MifarePlus,
+    /// ISO/IEC 14443-4 card, e.g. DESFire or a payment card
+    IsoDep,
+    Felica,
+    Unknown,
+}
+
+impl CardType {
+    /// Identifies Type A card by SAK, see NXP AN10833
+    pub fn from_sak(sak: u8) -> Self {
+        match sak {
+            0x00 => CardType::MifareUltralight,
+            0x09 => CardType::MifareMini,
+            0x08 | 0x28 => CardType::MifareClassic1K,
+            0x18 | 0x38 => CardType::MifareClassic4K,
+            0x10 | 0x11 => CardType::MifarePlus,
+            _ if sak & 0x20 != 0 => CardType::IsoDep,
+            _ => CardType::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardInfo {
+    pub technology: CardTechnology,
+    /// UID for Type A, PUPI for Type B, IDm for FeliCa
+    pub uid: Vec<u8>,
+    pub atqa: Option<[u8; 2]>,
+    pub sak: Option<u8>,
+    pub atqb: Option<Vec<u8>>,
+    /// Answer to select of ISO/IEC 14443-4 Type A card
+    pub ats: Option<Vec<u8>>,
+    pub card_type: CardType,
+}
+
+#[derive(Debug)]
+pub enum PollCardResult {
+    Canceled,
+    Card(CardInfo),
+}
+
+/// Direct communication with ISO/IEC 14443-4 cards, bypassing the EMV kernel
+///
+/// Implemented by `pcsc_nfc_reader::reader::PcscReader`, Uno8 documents no transparent mode.
+pub trait CardAccess {
+    /// Waits for a card and activates it up to ISO/IEC 14443-4
+    fn activate(&self, cancel: &CancellationToken) -> Result<PollCardResult, DeviceError>;
+
+    fn transceive(&self, command: &CommandApdu) -> Result<ResponseApdu, DeviceError>;
+
+    /// Deactivates the card and switches the field off
+    fn deactivate(&self) -> Result<(), DeviceError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_type_test() {
+        assert_eq!(CardType::from_sak(0x00), CardType::MifareUltralight);
+        assert_eq!(CardType::from_sak(0x08), CardType::MifareClassic1K);
+        assert_eq!(CardType::from_sak(0x18), CardType::MifareClassic4K);
+        assert_eq!(CardType::from_sak(0x20), CardType::IsoDep);
+        assert_eq!(CardType::from_sak(0x28), CardType::MifareClassic1K);
+        assert_eq!(CardType::from_sak(0x44), CardType::Unknown);
+    }
+}
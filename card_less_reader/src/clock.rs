@@ -0,0 +1,301 @@
+use crate::device;
+use crate::error;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use device::CardLessDevice;
+use error::DeviceError;
+
+/// Calendar date and time of the reader clock, years 2000-2099
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+impl DateTime {
+    /// Returns `None` if the date or time does not exist
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Option<Self> {
+        if !(2000..=2099).contains(&year)
+            || month == 0
+            || month > 12
+            || day == 0
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return None;
+        }
+        Some(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// Current time of the host in the time zone `utc_offset` seconds east of UTC
+    ///
+    /// Transaction Date (9A) and Time (9F21) are local time of the terminal,
+    /// so the reader clock is usually set to local time, not UTC.
+    pub fn now(utc_offset: i32) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_secs() as i64);
+        Self::from_timestamp(timestamp + utc_offset as i64)
+    }
+
+    /// Seconds since 1970-01-01 00:00:00, clamped to years 2000-2099
+    pub fn from_timestamp(timestamp: i64) -> Self {
+        let timestamp = timestamp.clamp(MIN_TIMESTAMP, MAX_TIMESTAMP);
+        let days = timestamp.div_euclid(86400);
+        let seconds = timestamp.rem_euclid(86400);
+
+        // Civil from days, H. Hinnant
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds % 3600 / 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        // Days from civil, H. Hinnant
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let month = self.month as i64;
+        let era = year.div_euclid(400);
+        let yoe = year - era * 400;
+        let doy =
+            (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
+    /// Transaction Date (9A) and Transaction Time (9F21) formats, YYMMDD and HHMMSS in BCD
+    pub fn from_bcd(date: [u8; 3], time: [u8; 3]) -> Option<Self> {
+        let bcd = |x: u8| match (x >> 4, x & 0x0F) {
+            (h, l) if h < 10 && l < 10 => Some(h * 10 + l),
+            _ => None,
+        };
+        Self::new(
+            2000 + bcd(date[0])? as u16,
+            bcd(date[1])?,
+            bcd(date[2])?,
+            bcd(time[0])?,
+            bcd(time[1])?,
+            bcd(time[2])?,
+        )
+    }
+
+    pub fn bcd_date(&self) -> [u8; 3] {
+        [
+            to_bcd((self.year % 100) as u8),
+            to_bcd(self.month),
+            to_bcd(self.day),
+        ]
+    }
+
+    pub fn bcd_time(&self) -> [u8; 3] {
+        [to_bcd(self.hour), to_bcd(self.minute), to_bcd(self.second)]
+    }
+
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+}
+
+/// 2000-01-01 00:00:00
+const MIN_TIMESTAMP: i64 = 946_684_800;
+/// 2099-12-31 23:59:59
+const MAX_TIMESTAMP: i64 = 4_102_444_799;
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    let leap_year = match (year % 4, year % 100, year % 400) {
+        (_, _, 0) => true,
+        (_, 0, _) => false,
+        (0, _, _) => true,
+        _ => false,
+    };
+    match month {
+        2 if leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn to_bcd(x: u8) -> u8 {
+    ((x / 10) << 4) | (x % 10)
+}
+
+/// Result of comparing the reader clock with the host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockDrift {
+    pub reader_time: DateTime,
+    pub host_time: DateTime,
+    /// Seconds the reader clock is ahead of the host, negative if behind
+    pub drift: i64,
+    /// The reader clock was set to `host_time`
+    pub corrected: bool,
+}
+
+/// Sets the reader clock to `host_time` if it drifted more than `max_drift`
+pub fn sync_clock<D>(
+    device: &mut D,
+    host_time: DateTime,
+    max_drift: Duration,
+) -> Result<ClockDrift, DeviceError>
+where
+    D: CardLessDevice + ?Sized,
+{
+    let reader_time = device.get_time()?;
+    let drift = reader_time.timestamp() - host_time.timestamp();
+
+    let corrected = drift.unsigned_abs() > max_drift.as_secs();
+    if corrected {
+        device.set_time(&host_time)?;
+    }
+
+    Ok(ClockDrift {
+        reader_time,
+        host_time,
+        drift,
+        corrected,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_time_test() {
+        let time = DateTime::new(2024, 2, 29, 23, 59, 58).unwrap();
+        assert_eq!(time.timestamp(), 1_709_251_198);
+        assert_eq!(DateTime::from_timestamp(1_709_251_198), time);
+        assert_eq!(DateTime::from_timestamp(1_709_251_200).month(), 3);
+
+        assert_eq!(time.bcd_date(), [0x24, 0x02, 0x29]);
+        assert_eq!(time.bcd_time(), [0x23, 0x59, 0x58]);
+        assert_eq!(
+            DateTime::from_bcd(time.bcd_date(), time.bcd_time()),
+            Some(time)
+        );
+
+        assert_eq!(DateTime::new(2023, 2, 29, 0, 0, 0), None);
+        assert_eq!(DateTime::from_bcd([0x24, 0x1A, 0x01], [0; 3]), None);
+        assert_eq!(DateTime::from_timestamp(0).year(), 2000);
+    }
+
+    #[test]
+    fn now_test() {
+        let utc = DateTime::now(0);
+        let local = DateTime::now(3 * 3600);
+        assert!((local.timestamp() - utc.timestamp() - 3 * 3600).abs() <= 1);
+    }
+
+    struct ClockDevice {
+        time: DateTime,
+        set: Vec<DateTime>,
+    }
+
+    impl CardLessDevice for ClockDevice {
+        fn get_sn(&self) -> Result<String, DeviceError> {
+            Ok("1_2_00000003".into())
+        }
+
+        fn get_time(&mut self) -> Result<DateTime, DeviceError> {
+            Ok(self.time)
+        }
+
+        fn set_time(&mut self, time: &DateTime) -> Result<(), DeviceError> {
+            self.set.push(*time);
+            self.time = *time;
+            Ok(())
+        }
+
+        fn poll_emv(
+            &mut self,
+            _purchase: Option<device::PollEmvPurchase>,
+            _cancel: &device::CancellationToken,
+        ) -> Result<device::PollEmvResult, DeviceError> {
+            Err(DeviceError::NotSupported)
+        }
+
+        fn ext_display(&mut self) -> Option<&dyn device::ExtDisplay> {
+            None
+        }
+
+        fn storage(&mut self) -> Option<&dyn crate::storage::Storage> {
+            None
+        }
+    }
+
+    #[test]
+    fn sync_clock_test() {
+        let host_time = DateTime::new(2026, 10, 18, 12, 0, 0).unwrap();
+        let mut device = ClockDevice {
+            time: DateTime::from_timestamp(host_time.timestamp() + 60),
+            set: vec![],
+        };
+
+        // Drift equal to the threshold is tolerated
+        let drift = device
+            .sync_clock(host_time, Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(drift.drift, 60);
+        assert!(!drift.corrected);
+        assert!(device.set.is_empty());
+
+        device.time = DateTime::from_timestamp(host_time.timestamp() - 61);
+        let drift = device
+            .sync_clock(host_time, Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(drift.drift, -61);
+        assert!(drift.corrected);
+        assert_eq!(device.set, vec![host_time]);
+        assert_eq!(device.get_time().unwrap(), host_time);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::error;
+use crate::tag_value;
+use crate::tlv_parser;
+
+use std::collections::BTreeMap;
+
+use error::*;
+use tag_value::{AlphaNumericSpecialTagValue, AlphaNumericTagValue, IntTagValue};
+use tlv_parser::TagValue;
+
+/// EMV data element format
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TagFormat {
+    /// `n`: BCD digits, right justified and padded with leading zeros
+    Numeric,
+    /// `b`: binary
+    Binary,
+    /// `an`: alphanumeric
+    AlphaNumeric,
+    /// `ans`: alphanumeric special
+    AlphaNumericSpecial,
+}
+
+/// Entry of the configuration tag dictionary
+#[derive(Debug)]
+pub struct TagSpec {
+    pub tag: usize,
+    pub name: &'static str,
+    pub format: TagFormat,
+    /// Length range in bytes
+    pub min_len: usize,
+    pub max_len: usize,
+}
+
+impl TagSpec {
+    const fn new(
+        tag: usize,
+        name: &'static str,
+        format: TagFormat,
+        min_len: usize,
+        max_len: usize,
+    ) -> Self {
+        Self {
+            tag,
+            name,
+            format,
+            min_len,
+            max_len,
+        }
+    }
+
+    pub fn validate(&self, value: &[u8]) -> Result<(), ConfigError> {
+        if value.len() < self.min_len || value.len() > self.max_len {
+            return Err(ConfigError::InvalidLength {
+                tag: self.tag,
+                name: self.name,
+                len: value.len(),
+            });
+        }
+
+        let valid = match self.format {
+            TagFormat::Numeric => decode_numeric(value).is_some(),
+            TagFormat::Binary => true,
+            TagFormat::AlphaNumeric => AlphaNumericTagValue::from_raw(value).is_ok(),
+            TagFormat::AlphaNumericSpecial => AlphaNumericSpecialTagValue::from_raw(value).is_ok(),
+        };
+        if !valid {
+            return Err(ConfigError::InvalidFormat {
+                tag: self.tag,
+                name: self.name,
+                value: value.to_vec(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Terminal and kernel parameters which may be configured
+#[rustfmt::skip]
+pub const TAG_DICTIONARY: &[TagSpec] = &[
+    TagSpec::new(0x9F1A, "Terminal Country Code", TagFormat::Numeric, 2, 2),
+    TagSpec::new(0x5F2A, "Transaction Currency Code", TagFormat::Numeric, 2, 2),
+    TagSpec::new(0x5F36, "Transaction Currency Exponent", TagFormat::Numeric, 1, 1),
+    TagSpec::new(0x9F35, "Terminal Type", TagFormat::Numeric, 1, 1),
+    TagSpec::new(0x9F33, "Terminal Capabilities", TagFormat::Binary, 3, 3),
+    TagSpec::new(0x9F40, "Additional Terminal Capabilities", TagFormat::Binary, 5, 5),
+    TagSpec::new(0x9F09, "Application Version Number", TagFormat::Binary, 2, 2),
+    TagSpec::new(0x9F66, "Terminal Transaction Qualifiers", TagFormat::Binary, 4, 4),
+    TagSpec::new(0x9F1B, "Terminal Floor Limit", TagFormat::Binary, 4, 4),
+    TagSpec::new(0xDF8123, "Reader Contactless Floor Limit", TagFormat::Numeric, 6, 6),
+    TagSpec::new(0xDF8124, "Reader Contactless Transaction Limit", TagFormat::Numeric, 6, 6),
+    TagSpec::new(0xDF8126, "Reader CVM Required Limit", TagFormat::Numeric, 6, 6),
+    TagSpec::new(0x9F15, "Merchant Category Code", TagFormat::Numeric, 2, 2),
+    TagSpec::new(0x9F16, "Merchant Identifier", TagFormat::AlphaNumericSpecial, 15, 15),
+    TagSpec::new(0x9F1C, "Terminal Identification", TagFormat::AlphaNumeric, 8, 8),
+    TagSpec::new(0x9F4E, "Merchant Name and Location", TagFormat::AlphaNumericSpecial, 1, 40),
+];
+
+pub fn tag_spec(tag: usize) -> Option<&'static TagSpec> {
+    TAG_DICTIONARY.iter().find(|x| x.tag == tag)
+}
+
+/// Which transactions the parameters apply to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigScope {
+    Global,
+    /// Application Identifier (9F06)
+    Aid(Vec<u8>),
+    /// Kernel Identifier (9F2A)
+    Kernel(u8),
+}
+
+/// Set of configuration parameters
+///
+/// Setters do not check values, `validate` does it against `TAG_DICTIONARY`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TerminalConfig {
+    params: BTreeMap<usize, Vec<u8>>,
+}
+
+impl TerminalConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_param(mut self, tag: usize, value: Vec<u8>) -> Self {
+        self.params.insert(tag, value);
+        self
+    }
+
+    pub fn with_country_code(self, code: u16) -> Self {
+        self.with_numeric(0x9F1A, code as u64, 3)
+    }
+
+    pub fn with_terminal_type(self, terminal_type: u8) -> Self {
+        self.with_numeric(0x9F35, terminal_type as u64, 2)
+    }
+
+    pub fn with_terminal_capabilities(self, capabilities: [u8; 3]) -> Self {
+        self.with_param(0x9F33, capabilities.to_vec())
+    }
+
+    pub fn with_additional_terminal_capabilities(self, capabilities: [u8; 5]) -> Self {
+        self.with_param(0x9F40, capabilities.to_vec())
+    }
+
+    pub fn with_floor_limit(self, limit: u32) -> Self {
+        self.with_param(0x9F1B, limit.to_be_bytes().to_vec())
+    }
+
+    pub fn with_contactless_floor_limit(self, limit: u64) -> Self {
+        self.with_numeric(0xDF8123, limit, 12)
+    }
+
+    pub fn with_transaction_limit(self, limit: u64) -> Self {
+        self.with_numeric(0xDF8124, limit, 12)
+    }
+
+    pub fn with_cvm_limit(self, limit: u64) -> Self {
+        self.with_numeric(0xDF8126, limit, 12)
+    }
+
+    pub fn with_merchant_category_code(self, code: u16) -> Self {
+        self.with_numeric(0x9F15, code as u64, 4)
+    }
+
+    pub fn with_merchant_id(self, id: &str) -> Self {
+        self.with_param(0x9F16, id.as_bytes().to_vec())
+    }
+
+    pub fn with_terminal_id(self, id: &str) -> Self {
+        self.with_param(0x9F1C, id.as_bytes().to_vec())
+    }
+
+    fn with_numeric(self, tag: usize, value: u64, digits: usize) -> Self {
+        self.with_param(tag, IntTagValue::new((value, digits)).bytes())
+    }
+
+    pub fn param(&self, tag: usize) -> Option<&[u8]> {
+        self.params.get(&tag).map(|x| &x[..])
+    }
+
+    pub fn params(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        self.params.iter().map(|(tag, value)| (*tag, &value[..]))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    pub fn country_code(&self) -> Option<u16> {
+        self.numeric(0x9F1A).map(|x| x as u16)
+    }
+
+    pub fn terminal_type(&self) -> Option<u8> {
+        self.numeric(0x9F35).map(|x| x as u8)
+    }
+
+    pub fn floor_limit(&self) -> Option<u32> {
+        match self.param(0x9F1B) {
+            Some(&[a, b, c, d]) => Some(u32::from_be_bytes([a, b, c, d])),
+            _ => None,
+        }
+    }
+
+    pub fn contactless_floor_limit(&self) -> Option<u64> {
+        self.numeric(0xDF8123)
+    }
+
+    pub fn transaction_limit(&self) -> Option<u64> {
+        self.numeric(0xDF8124)
+    }
+
+    pub fn cvm_limit(&self) -> Option<u64> {
+        self.numeric(0xDF8126)
+    }
+
+    pub fn merchant_id(&self) -> Option<&str> {
+        self.param(0x9F16).and_then(|x| std::str::from_utf8(x).ok())
+    }
+
+    pub fn terminal_id(&self) -> Option<&str> {
+        self.param(0x9F1C).and_then(|x| std::str::from_utf8(x).ok())
+    }
+
+    fn numeric(&self, tag: usize) -> Option<u64> {
+        self.param(tag).and_then(decode_numeric)
+    }
+
+    /// Checks that every parameter is known and well formed
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (tag, value) in self.params() {
+            match tag_spec(tag) {
+                Some(spec) => spec.validate(value)?,
+                None => return Err(ConfigError::UnknownTag(tag)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads and writes terminal and kernel parameters of the device
+pub trait Configuration {
+    /// Returns parameters of `scope` the device has
+    fn read_config(&self, scope: &ConfigScope) -> Result<TerminalConfig, DeviceError>;
+
+    /// Validates and updates parameters of `scope`, others stay unchanged
+    fn write_config(&self, scope: &ConfigScope, config: &TerminalConfig)
+        -> Result<(), DeviceError>;
+}
+
+/// Decodes format `n`, `None` if a nibble is not a digit
+fn decode_numeric(raw: &[u8]) -> Option<u64> {
+    raw.iter()
+        .flat_map(|x| vec![x >> 4, x & 0x0F])
+        .try_fold(0_u64, |sum, digit| match digit {
+            0..=9 => sum.checked_mul(10)?.checked_add(digit as u64),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terminal_config_test() {
+        let config = TerminalConfig::new()
+            .with_country_code(643)
+            .with_terminal_type(22)
+            .with_floor_limit(0)
+            .with_transaction_limit(1_000_000)
+            .with_cvm_limit(100_000)
+            .with_merchant_id("MERCHANT 000001")
+            .with_terminal_id("TID00001");
+
+        assert_eq!(config.param(0x9F1A), Some(&[0x06, 0x43][..]));
+        assert_eq!(config.param(0x9F35), Some(&[0x22][..]));
+        assert_eq!(
+            config.param(0xDF8124),
+            Some(&[0x00, 0x00, 0x01, 0x00, 0x00, 0x00][..])
+        );
+        assert_eq!(config.country_code(), Some(643));
+        assert_eq!(config.transaction_limit(), Some(1_000_000));
+        assert_eq!(config.terminal_id(), Some("TID00001"));
+        config.validate().unwrap();
+
+        assert!(matches!(
+            config.clone().with_terminal_id("TID 0001").validate(),
+            Err(ConfigError::InvalidFormat { tag: 0x9F1C, .. })
+        ));
+        assert!(matches!(
+            config.clone().with_merchant_id("SHORT").validate(),
+            Err(ConfigError::InvalidLength { tag: 0x9F16, .. })
+        ));
+        assert!(matches!(
+            config
+                .clone()
+                .with_param(0x9F1A, vec![0x0A, 0x43])
+                .validate(),
+            Err(ConfigError::InvalidFormat { tag: 0x9F1A, .. })
+        ));
+        assert!(matches!(
+            config.with_param(0x9F02, vec![0x00; 6]).validate(),
+            Err(ConfigError::UnknownTag(0x9F02))
+        ));
+    }
+}
@@ -1,24 +1,133 @@
+use crate::capk;
+use crate::card;
+use crate::clock;
+use crate::config;
+use crate::device_info;
 use crate::error;
-use crate::tlv_parser;
+use crate::event;
+use crate::outcome;
+use crate::storage;
 
+use capk::CapkStore;
+use card::{CardAccess, PollCardResult};
+use clock::{ClockDrift, DateTime};
+use config::Configuration;
+use device_info::{Capability, CapabilitySet, DeviceInfo};
 use error::*;
-use std::{io::{Read, Write, BufReader}, sync::{atomic::AtomicBool, Arc}};
-use tlv_parser::Tlv;
-
-type StorageResult<T> = Result<T, StorageError>;
+use event::EventSubscription;
+use outcome::PollOutcome;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+use storage::Storage;
 
 pub trait CardLessDevice {
     fn get_sn(&self) -> Result<String, DeviceError>;
 
+    /// Model, versions, kernels and hardware of the device
+    ///
+    /// Not implemented for Uno8, its protocol documents no version query, only `get_sn`.
+    fn device_info(&mut self) -> Result<DeviceInfo, DeviceError> {
+        Err(DeviceError::NotSupported)
+    }
+
+    /// Current time of the reader clock used for 9A and 9F21
+    ///
+    /// Not implemented for Uno8, its protocol documents no clock instruction.
+    fn get_time(&mut self) -> Result<DateTime, DeviceError> {
+        Err(DeviceError::NotSupported)
+    }
+
+    fn set_time(&mut self, _time: &DateTime) -> Result<(), DeviceError> {
+        Err(DeviceError::NotSupported)
+    }
+
+    /// Sets the reader clock to `host_time` if it drifted more than `max_drift`
+    fn sync_clock(
+        &mut self,
+        host_time: DateTime,
+        max_drift: Duration,
+    ) -> Result<ClockDrift, DeviceError> {
+        clock::sync_clock(self, host_time, max_drift)
+    }
+
+    /// Optional features the application may offer with this device
+    fn capabilities(&mut self) -> CapabilitySet {
+        let mut capabilities = CapabilitySet::new();
+        if self.ext_display().is_some() {
+            capabilities.insert(Capability::ExtDisplay);
+        }
+        if self.storage().is_some() {
+            capabilities.insert(Capability::Storage);
+        }
+        if self.card_access().is_some() {
+            capabilities.insert(Capability::CardAccess);
+        }
+        if self.configuration().is_some() {
+            capabilities.insert(Capability::Configuration);
+        }
+        if self.capk_store().is_some() {
+            capabilities.insert(Capability::CapkStore);
+        }
+        if self.user_interface().is_some() {
+            capabilities.insert(Capability::UserInterface);
+        }
+        capabilities
+    }
+
     fn poll_emv(
         &mut self,
         purchase: Option<PollEmvPurchase>,
-        cancel_flag: Arc<AtomicBool>,
+        cancel: &CancellationToken,
     ) -> Result<PollEmvResult, DeviceError>;
 
+    /// Performs refund, void, pre-authorization and other transactions
+    ///
+    /// `Void` and `Completion` need the data of the original transaction,
+    /// so by default only requests expressible as `PollEmvPurchase` are supported.
+    /// No driver passes the original transaction to the card yet, the Uno8 protocol
+    /// has no template for it, so both return `DeviceError::NotSupported`.
+    fn transaction(
+        &mut self,
+        request: TransactionRequest,
+        cancel: &CancellationToken,
+    ) -> Result<PollEmvResult, DeviceError> {
+        match request.original() {
+            Some(_) => Err(DeviceError::NotSupported),
+            None => self.poll_emv(Some(request.to_purchase()), cancel),
+        }
+    }
+
+    /// Waits for any contactless card, e.g. a badge, without starting EMV transaction
+    ///
+    /// No driver implements it yet, the Uno8 protocol polls cards only in the EMV macro.
+    fn poll_card(&mut self, _cancel: &CancellationToken) -> Result<PollCardResult, DeviceError> {
+        Err(DeviceError::NotSupported)
+    }
+
     fn ext_display(&mut self) -> Option<&dyn ExtDisplay>;
 
+    fn card_access(&mut self) -> Option<&dyn CardAccess> {
+        None
+    }
+
+    fn configuration(&mut self) -> Option<&dyn Configuration> {
+        None
+    }
+
+    fn capk_store(&mut self) -> Option<&dyn CapkStore> {
+        None
+    }
+
+    fn user_interface(&mut self) -> Option<&dyn UserInterface> {
+        None
+    }
+
     fn storage(&mut self) -> Option<&dyn Storage>;
+
+    /// Subscribes to display messages, logs and other events of the device
+    fn subscribe(&self) -> EventSubscription {
+        EventSubscription::closed()
+    }
 }
 
 pub trait ExtDisplay {
@@ -27,26 +136,219 @@ pub trait ExtDisplay {
     fn set_display_mode(&self, mode: &ExtDisplayMode) -> Result<(), DeviceError>;
 }
 
+/// Status LEDs, buzzer and backlight of the reader
+///
+/// No reader driver implements it yet, the Uno8 protocol documents no LED or buzzer instruction.
+pub trait UserInterface {
+    fn set_leds(&self, leds: &[LedState; LED_COUNT]) -> Result<(), DeviceError>;
+
+    /// Plays `tones` one after another, a tone of 0 Hz is a pause
+    fn beep(&self, tones: &[Tone]) -> Result<(), DeviceError>;
+
+    /// `level` is brightness in percent, 0 switches the backlight off
+    fn set_backlight(&self, level: u8) -> Result<(), DeviceError>;
+
+    /// Shows `signal` with LEDs and tones of EMV Contactless Book A, 9.2
+    fn signal(&self, signal: UiSignal) -> Result<(), DeviceError> {
+        use LedState::*;
+
+        let (leds, tones) = match signal {
+            UiSignal::Idle => (
+                [Blink { on_ms: 200, off_ms: 4800 }, Off, Off, Off],
+                vec![],
+            ),
+            UiSignal::PresentCard => ([On, Off, Off, Off], vec![]),
+            UiSignal::Processing => ([On, On, Off, Off], vec![]),
+            // Card read successfully, the tone tells the card may be removed
+            UiSignal::RemoveCard => ([On, On, On, Off], vec![Tone::SUCCESS]),
+            // The fourth LED completes the sequence, the tone was played on card removal
+            UiSignal::Success => ([On, On, On, On], vec![]),
+            UiSignal::Alert => (
+                [Off, Off, Off, Off],
+                vec![Tone::ALERT, Tone::pause(200), Tone::ALERT],
+            ),
+        };
+
+        self.set_leds(&leds)?;
+        if !tones.is_empty() {
+            self.beep(&tones)?;
+        }
+        Ok(())
+    }
+}
+
+/// Transaction Type (9C), the first two digits of the ISO 8583 Processing Code
+///
+/// There is no code for pre-authorization, it is a purchase sent as an authorization
+/// request, see `TransactionRequest::PreAuthorization`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransactionType {
+    Purchase,
+    Cash,
+    PurchaseWithCashback,
+    Refund,
+    Payment,
+    AvailableFundsInquiry,
+    BalanceInquiry,
+    Transfer,
+    Other(u8),
+}
+
+impl TransactionType {
+    /// Returns the value of Transaction Type (tag 9C)
+    pub fn code(&self) -> u8 {
+        match self {
+            TransactionType::Purchase => 0x00,
+            TransactionType::Cash => 0x01,
+            TransactionType::PurchaseWithCashback => 0x09,
+            TransactionType::Refund => 0x20,
+            TransactionType::Payment => 0x28,
+            TransactionType::AvailableFundsInquiry => 0x30,
+            TransactionType::BalanceInquiry => 0x31,
+            TransactionType::Transfer => 0x40,
+            TransactionType::Other(code) => *code,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => TransactionType::Purchase,
+            0x01 => TransactionType::Cash,
+            0x09 => TransactionType::PurchaseWithCashback,
+            0x20 => TransactionType::Refund,
+            0x28 => TransactionType::Payment,
+            0x30 => TransactionType::AvailableFundsInquiry,
+            0x31 => TransactionType::BalanceInquiry,
+            0x40 => TransactionType::Transfer,
+            _ => TransactionType::Other(code),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct PollEmvPurchase {
-    pub p_type: u8,
+    pub transaction_type: TransactionType,
     pub currency_code: u16,
+    /// Amount, Authorised (tag 9F02). For cashback it includes the cashback amount
     pub amount: u64,
+    /// Amount, Other (tag 9F03)
+    pub amount_other: Option<u64>,
 }
 
 impl PollEmvPurchase {
-    pub fn new(p_type: u8, currency_code: u16, amount: u64) -> Self {
+    pub fn new(transaction_type: TransactionType, currency_code: u16, amount: u64) -> Self {
         Self {
-            p_type,
+            transaction_type,
             currency_code,
             amount,
+            amount_other: None,
+        }
+    }
+
+    pub fn with_amount_other(mut self, amount_other: u64) -> Self {
+        self.amount_other = Some(amount_other);
+        self
+    }
+}
+
+/// Reference to a previously performed transaction
+#[derive(Debug, Clone)]
+pub struct OriginalTransaction {
+    /// Transaction Type (9C) of the original transaction
+    pub transaction_type: TransactionType,
+    /// Amount, Authorised (9F02) of the original transaction
+    pub amount: u64,
+    /// Transaction Date (9A), `YYMMDD` in BCD
+    pub transaction_date: [u8; 3],
+    /// Transaction Sequence Counter (9F41)
+    pub sequence_counter: u32,
+}
+
+#[derive(Debug)]
+pub enum TransactionRequest {
+    Purchase(PollEmvPurchase),
+    Refund {
+        currency_code: u16,
+        amount: u64,
+    },
+    /// Reserves `amount` on the card account, captured later by `Completion`
+    ///
+    /// The card sees a purchase, the acquirer host sends it as an authorization only.
+    PreAuthorization {
+        currency_code: u16,
+        amount: u64,
+    },
+    /// Captures the final `amount` of a pre-authorization
+    Completion {
+        currency_code: u16,
+        amount: u64,
+        original: OriginalTransaction,
+    },
+    /// Reverses a transaction, the card is read again to match the original
+    ///
+    /// The card sees the original transaction type,
+    /// the acquirer host sends the result as a reversal of the original.
+    Void {
+        currency_code: u16,
+        original: OriginalTransaction,
+    },
+}
+
+impl TransactionRequest {
+    pub fn transaction_type(&self) -> TransactionType {
+        match self {
+            TransactionRequest::Purchase(purchase) => purchase.transaction_type,
+            TransactionRequest::Refund { .. } => TransactionType::Refund,
+            TransactionRequest::PreAuthorization { .. } | TransactionRequest::Completion { .. } => {
+                TransactionType::Purchase
+            }
+            TransactionRequest::Void { original, .. } => original.transaction_type,
+        }
+    }
+
+    pub fn original(&self) -> Option<&OriginalTransaction> {
+        match self {
+            TransactionRequest::Completion { original, .. }
+            | TransactionRequest::Void { original, .. } => Some(original),
+            _ => None,
+        }
+    }
+
+    /// Returns template data of the request, the original transaction is not included
+    pub fn to_purchase(&self) -> PollEmvPurchase {
+        match self {
+            TransactionRequest::Purchase(purchase) => PollEmvPurchase {
+                amount_other: purchase.amount_other,
+                ..PollEmvPurchase::new(
+                    purchase.transaction_type,
+                    purchase.currency_code,
+                    purchase.amount,
+                )
+            },
+            TransactionRequest::Refund {
+                currency_code,
+                amount,
+            }
+            | TransactionRequest::PreAuthorization {
+                currency_code,
+                amount,
+            }
+            | TransactionRequest::Completion {
+                currency_code,
+                amount,
+                ..
+            } => PollEmvPurchase::new(self.transaction_type(), *currency_code, *amount),
+            TransactionRequest::Void {
+                currency_code,
+                original,
+            } => PollEmvPurchase::new(self.transaction_type(), *currency_code, original.amount),
         }
     }
 }
 
 pub enum PollEmvResult {
     Canceled,
-    Success(Tlv),
+    Outcome(PollOutcome),
 }
 
 #[derive(Copy, Clone)]
@@ -56,29 +358,522 @@ pub enum ExtDisplayMode {
     Full,
 }
 
-pub trait Storage {
-    fn dir_exist(&self, path: String) -> StorageResult<()>;
+pub const LED_COUNT: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LedState {
+    Off,
+    On,
+    Blink { on_ms: u16, off_ms: u16 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tone {
+    pub frequency_hz: u16,
+    pub duration_ms: u16,
+}
+
+impl Tone {
+    /// Success tone of EMV Contactless Book A
+    pub const SUCCESS: Tone = Tone::new(1500, 500);
+    /// Alert tone of EMV Contactless Book A, played twice
+    pub const ALERT: Tone = Tone::new(750, 200);
+
+    pub const fn new(frequency_hz: u16, duration_ms: u16) -> Self {
+        Self {
+            frequency_hz,
+            duration_ms,
+        }
+    }
+
+    pub const fn pause(duration_ms: u16) -> Self {
+        Self::new(0, duration_ms)
+    }
+}
+
+/// Reader state shown to the cardholder
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UiSignal {
+    Idle,
+    PresentCard,
+    Processing,
+    RemoveCard,
+    Success,
+    Alert,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CancelReason {
+    Requested,
+    DeadlineExceeded,
+    Other(String),
+}
+
+type CancelCallback = Box<dyn FnOnce(&CancelReason) + Send>;
 
-    fn get_dir_list(&self, path: String) -> StorageResult<Vec<String>>;
+struct TokenState {
+    reason: Option<CancelReason>,
+    callbacks: Vec<(u64, CancelCallback)>,
+    next_callback_id: u64,
+    children: Vec<Weak<TokenInner>>,
+}
 
-    fn create_dir(&self, path: String) -> StorageResult<()>;
+struct TokenInner {
+    state: Mutex<TokenState>,
+    cancelled: Condvar,
+    deadline: Option<Instant>,
+}
 
-    fn delete_dir(&self, path: String) -> StorageResult<()>;
+/// Cancels long-running device operations
+///
+/// Clones share the same state. Cancelling a token cancels all of its children,
+/// a token is also cancelled once its deadline has passed and it is checked.
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<TokenInner>,
+}
 
-    fn file_exist(&self, file_path: String) -> StorageResult<()>;
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::with_optional_deadline(None)
+    }
 
-    fn get_file_list(&self, path: String) -> StorageResult<Vec<String>>;
+    /// Token cancelled with `CancelReason::DeadlineExceeded` once `deadline` has passed
+    ///
+    /// No timer is started: the deadline takes effect when `reason`, `is_cancelled`
+    /// or `wait_timeout` is called after it, and only then `on_cancel` callbacks run.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self::with_optional_deadline(Some(deadline))
+    }
 
-    fn delete_file(&self, file_path: String) -> StorageResult<()>;
+    /// Same as `with_deadline` with the deadline `timeout` from now
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self::with_deadline(Instant::now() + timeout)
+    }
 
-    fn open_read_file(&self, file_path: String) -> StorageResult<&dyn Read>;
+    fn with_optional_deadline(deadline: Option<Instant>) -> Self {
+        Self {
+            inner: Arc::new(TokenInner {
+                state: Mutex::new(TokenState {
+                    reason: None,
+                    callbacks: vec![],
+                    next_callback_id: 0,
+                    children: vec![],
+                }),
+                cancelled: Condvar::new(),
+                deadline,
+            }),
+        }
+    }
 
-    fn open_write_file(&self, file_path: String) -> StorageResult<&dyn Write>;
-/*
-    fn read_file(&self, file_path: String) -> StorageResult<Vec<u8>> {
-        let mut buf_reader = BufReader::new(
-            self.open_read_file(file_path));
+    /// Creates token which is cancelled together with this one
+    pub fn child(&self) -> Self {
+        self.child_with_optional_deadline(None)
+    }
 
-       buf_reader.     
-    }*/
-}
\ No newline at end of file
+    /// Creates child token with its own deadline, the earliest deadline wins
+    pub fn child_with_timeout(&self, timeout: Duration) -> Self {
+        self.child_with_optional_deadline(Some(Instant::now() + timeout))
+    }
+
+    fn child_with_optional_deadline(&self, deadline: Option<Instant>) -> Self {
+        let deadline = match (self.inner.deadline, deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let child = Self::with_optional_deadline(deadline);
+
+        let reason = {
+            let mut state = self.inner.state.lock().unwrap();
+            if state.reason.is_none() {
+                state.children.retain(|x| x.strong_count() > 0);
+                state.children.push(Arc::downgrade(&child.inner));
+            }
+            state.reason.clone()
+        };
+        if let Some(reason) = reason {
+            child.cancel_with(reason);
+        }
+
+        child
+    }
+
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Requested)
+    }
+
+    pub fn cancel_with(&self, reason: CancelReason) {
+        Self::cancel_inner(&self.inner, reason)
+    }
+
+    fn cancel_inner(inner: &Arc<TokenInner>, reason: CancelReason) {
+        let (callbacks, children) = {
+            let mut state = inner.state.lock().unwrap();
+            if state.reason.is_some() {
+                return;
+            }
+            state.reason = Some(reason.clone());
+            inner.cancelled.notify_all();
+            (
+                std::mem::take(&mut state.callbacks),
+                std::mem::take(&mut state.children),
+            )
+        };
+
+        for (_, callback) in callbacks {
+            callback(&reason);
+        }
+        for child in children.iter().filter_map(|x| x.upgrade()) {
+            Self::cancel_inner(&child, reason.clone());
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.reason().is_some()
+    }
+
+    pub fn reason(&self) -> Option<CancelReason> {
+        if let Some(reason) = &self.inner.state.lock().unwrap().reason {
+            return Some(reason.clone());
+        }
+
+        match self.inner.deadline {
+            Some(deadline) if deadline <= Instant::now() => {
+                self.cancel_with(CancelReason::DeadlineExceeded);
+                self.inner.state.lock().unwrap().reason.clone()
+            }
+            _ => None,
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.inner.deadline
+    }
+
+    /// Returns time left before the deadline
+    pub fn remaining(&self) -> Option<Duration> {
+        self.inner
+            .deadline
+            .map(|x| x.saturating_duration_since(Instant::now()))
+    }
+
+    /// Blocks until the token is cancelled or `timeout` elapsed, returns `true` if cancelled
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let timeout = match self.remaining() {
+            Some(remaining) => remaining.min(timeout),
+            None => timeout,
+        };
+
+        let state = self.inner.state.lock().unwrap();
+        let _ = self
+            .inner
+            .cancelled
+            .wait_timeout_while(state, timeout, |x| x.reason.is_none())
+            .unwrap();
+
+        self.is_cancelled()
+    }
+
+    /// Calls `f` once the token is cancelled, immediately if it is already cancelled
+    ///
+    /// A passed deadline fires `f` only when it is checked, see `with_deadline`.
+    /// The callback is removed when the returned registration is dropped.
+    pub fn on_cancel(&self, f: impl FnOnce(&CancelReason) + Send + 'static) -> CancelRegistration {
+        let id = {
+            let mut state = self.inner.state.lock().unwrap();
+            match &state.reason {
+                Some(reason) => {
+                    let reason = reason.clone();
+                    drop(state);
+                    f(&reason);
+                    None
+                }
+                None => {
+                    let id = state.next_callback_id;
+                    state.next_callback_id += 1;
+                    state.callbacks.push((id, Box::new(f)));
+                    Some(id)
+                }
+            }
+        };
+
+        CancelRegistration {
+            inner: Arc::downgrade(&self.inner),
+            id,
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct CancelRegistration {
+    inner: Weak<TokenInner>,
+    id: Option<u64>,
+}
+
+impl Drop for CancelRegistration {
+    fn drop(&mut self) {
+        if let (Some(inner), Some(id)) = (self.inner.upgrade(), self.id) {
+            inner
+                .state
+                .lock()
+                .unwrap()
+                .callbacks
+                .retain(|(x, _)| *x != id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    struct RecordingUi {
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl UserInterface for RecordingUi {
+        fn set_leds(&self, leds: &[LedState; LED_COUNT]) -> Result<(), DeviceError> {
+            self.calls.lock().unwrap().push(format!("{:?}", leds));
+            Ok(())
+        }
+
+        fn beep(&self, tones: &[Tone]) -> Result<(), DeviceError> {
+            self.calls.lock().unwrap().push(format!("{:?}", tones));
+            Ok(())
+        }
+
+        fn set_backlight(&self, _level: u8) -> Result<(), DeviceError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ui_signal_test() {
+        let ui = RecordingUi {
+            calls: Mutex::new(vec![]),
+        };
+
+        ui.signal(UiSignal::Processing).unwrap();
+        ui.signal(UiSignal::RemoveCard).unwrap();
+        ui.signal(UiSignal::Success).unwrap();
+        ui.signal(UiSignal::Alert).unwrap();
+        assert_eq!(
+            *ui.calls.lock().unwrap(),
+            vec![
+                format!("{:?}", [LedState::On, LedState::On, LedState::Off, LedState::Off]),
+                format!("{:?}", [LedState::On, LedState::On, LedState::On, LedState::Off]),
+                format!("{:?}", [Tone::SUCCESS]),
+                format!("{:?}", [LedState::On; LED_COUNT]),
+                format!("{:?}", [LedState::Off; LED_COUNT]),
+                format!("{:?}", [Tone::ALERT, Tone::pause(200), Tone::ALERT]),
+            ]
+        );
+    }
+
+    #[test]
+    fn cancel_test() {
+        let token = CancellationToken::new();
+        let child = token.child();
+        let grandchild = child.child();
+
+        assert!(!grandchild.is_cancelled());
+
+        token.cancel_with(CancelReason::Other("shutdown".into()));
+        assert_eq!(
+            grandchild.reason(),
+            Some(CancelReason::Other("shutdown".into()))
+        );
+
+        // a child of a cancelled token starts cancelled
+        assert_eq!(token.child().reason(), token.reason());
+        // cancelling a child does not touch the parent
+        let token = CancellationToken::new();
+        token.child().cancel();
+        assert!(!token.is_cancelled());
+    }
+
+    #[test]
+    fn deadline_test() {
+        let token = CancellationToken::with_timeout(Duration::from_millis(20));
+        let child = token.child_with_timeout(Duration::from_secs(60));
+
+        assert!(child.deadline() == token.deadline());
+        assert!(!child.wait_timeout(Duration::from_millis(1)));
+        assert!(child.wait_timeout(Duration::from_secs(60)));
+        assert_eq!(child.reason(), Some(CancelReason::DeadlineExceeded));
+    }
+
+    #[test]
+    fn notify_test() {
+        let token = CancellationToken::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let calls_ref = calls.clone();
+        let _registration = token.on_cancel(move |_| {
+            calls_ref.fetch_add(1, Ordering::SeqCst);
+        });
+        let calls_ref = calls.clone();
+        drop(token.on_cancel(move |_| {
+            calls_ref.fetch_add(10, Ordering::SeqCst);
+        }));
+
+        let token_ref = token.clone();
+        let waiter = thread::spawn(move || token_ref.wait_timeout(Duration::from_secs(60)));
+        token.cancel();
+        token.cancel();
+
+        assert!(waiter.join().unwrap());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn transaction_request_test() {
+        let original = OriginalTransaction {
+            transaction_type: TransactionType::PurchaseWithCashback,
+            amount: 1500,
+            transaction_date: [0x26, 0x10, 0x18],
+            sequence_counter: 42,
+        };
+
+        let void = TransactionRequest::Void {
+            currency_code: 643,
+            original: original.clone(),
+        };
+        let purchase = void.to_purchase();
+        assert_eq!(
+            purchase.transaction_type,
+            TransactionType::PurchaseWithCashback
+        );
+        assert_eq!(purchase.amount, 1500);
+        assert!(void.original().is_some());
+
+        let refund = TransactionRequest::Refund {
+            currency_code: 643,
+            amount: 700,
+        };
+        assert_eq!(refund.to_purchase().transaction_type.code(), 0x20);
+        assert!(refund.original().is_none());
+
+        let pre_authorization = TransactionRequest::PreAuthorization {
+            currency_code: 643,
+            amount: 5000,
+        };
+        assert_eq!(
+            pre_authorization.to_purchase().transaction_type,
+            TransactionType::Purchase
+        );
+    }
+
+    /// Records purchases passed to `poll_emv`
+    struct PollingDevice {
+        purchases: Vec<PollEmvPurchase>,
+    }
+
+    impl CardLessDevice for PollingDevice {
+        fn get_sn(&self) -> Result<String, DeviceError> {
+            Ok("1_2_00000003".into())
+        }
+
+        fn poll_emv(
+            &mut self,
+            purchase: Option<PollEmvPurchase>,
+            _cancel: &CancellationToken,
+        ) -> Result<PollEmvResult, DeviceError> {
+            self.purchases.extend(purchase);
+            Ok(PollEmvResult::Canceled)
+        }
+
+        fn ext_display(&mut self) -> Option<&dyn ExtDisplay> {
+            None
+        }
+
+        fn storage(&mut self) -> Option<&dyn Storage> {
+            None
+        }
+    }
+
+    #[test]
+    fn default_transaction_test() {
+        let mut device = PollingDevice { purchases: vec![] };
+        let cancel = CancellationToken::new();
+
+        device
+            .transaction(
+                TransactionRequest::Refund {
+                    currency_code: 643,
+                    amount: 700,
+                },
+                &cancel,
+            )
+            .unwrap();
+        device
+            .transaction(
+                TransactionRequest::PreAuthorization {
+                    currency_code: 643,
+                    amount: 5000,
+                },
+                &cancel,
+            )
+            .unwrap();
+
+        let original = OriginalTransaction {
+            transaction_type: TransactionType::Purchase,
+            amount: 5000,
+            transaction_date: [0x26, 0x10, 0x18],
+            sequence_counter: 43,
+        };
+        assert!(matches!(
+            device.transaction(
+                TransactionRequest::Completion {
+                    currency_code: 643,
+                    amount: 4200,
+                    original: original.clone(),
+                },
+                &cancel,
+            ),
+            Err(DeviceError::NotSupported)
+        ));
+        assert!(matches!(
+            device.transaction(
+                TransactionRequest::Void {
+                    currency_code: 643,
+                    original,
+                },
+                &cancel,
+            ),
+            Err(DeviceError::NotSupported)
+        ));
+
+        let polled: Vec<(TransactionType, u64)> = device
+            .purchases
+            .iter()
+            .map(|x| (x.transaction_type, x.amount))
+            .collect();
+        assert_eq!(
+            polled,
+            vec![
+                (TransactionType::Refund, 700),
+                (TransactionType::Purchase, 5000)
+            ]
+        );
+    }
+
+    #[test]
+    fn transaction_type_test() {
+        for code in 0..=0xFF {
+            assert_eq!(TransactionType::from_code(code).code(), code);
+        }
+        assert_eq!(TransactionType::from_code(0x09), TransactionType::PurchaseWithCashback);
+        assert_eq!(TransactionType::from_code(0x02), TransactionType::Other(0x02));
+        assert_eq!(TransactionType::from_code(0x03), TransactionType::Other(0x03));
+        assert_eq!(TransactionType::Refund.code(), 0x20);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeSet;
+use std::iter::FromIterator;
+
+/// Contactless kernel of the reader
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelInfo {
+    /// Kernel Identifier (9F2A), e.g. 02 for C-2
+    pub id: u8,
+    pub version: String,
+}
+
+impl KernelInfo {
+    pub fn name(&self) -> &'static str {
+        match self.id {
+            0x01 => "C-1",
+            0x02 => "C-2 Mastercard",
+            0x03 => "C-3 Visa",
+            0x04 => "C-4 American Express",
+            0x05 => "C-5 JCB",
+            0x06 => "C-6 Discover",
+            0x07 => "C-7 UnionPay",
+            0x08 => "C-8",
+            _ => "Proprietary",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Interface {
+    UsbHid,
+    Serial,
+    Bluetooth,
+    Ethernet,
+    Wifi,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HardwareOption {
+    Display,
+    Buzzer,
+    Leds,
+    Backlight,
+    SamSlot,
+    ContactReader,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub model: String,
+    pub serial_number: String,
+    pub firmware_version: String,
+    pub bootloader_version: Option<String>,
+    pub kernels: Vec<KernelInfo>,
+    pub interfaces: Vec<Interface>,
+    pub hardware_options: Vec<HardwareOption>,
+}
+
+/// Optional feature of `CardLessDevice`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capability {
+    ExtDisplay,
+    Storage,
+    CardAccess,
+    Configuration,
+    CapkStore,
+    UserInterface,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapabilitySet {
+    capabilities: BTreeSet<Capability>,
+}
+
+impl CapabilitySet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, capability: Capability) {
+        self.capabilities.insert(capability);
+    }
+
+    pub fn contains(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Capability> + '_ {
+        self.capabilities.iter().copied()
+    }
+}
+
+impl FromIterator<Capability> for CapabilitySet {
+    fn from_iter<I: IntoIterator<Item = Capability>>(iter: I) -> Self {
+        Self {
+            capabilities: iter.into_iter().collect(),
+        }
+    }
+}
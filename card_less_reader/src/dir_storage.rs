@@ -0,0 +1,256 @@
+use crate::error;
+use crate::storage;
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use error::StorageError;
+use storage::{split_file_path, split_path, Storage, StorageResult};
+
+/// `Storage` backed by a directory of the local file system
+///
+/// Symbolic links are followed only if they point inside the root directory.
+pub struct DirStorage {
+    root: PathBuf,
+}
+
+impl DirStorage {
+    pub fn new(root: impl Into<PathBuf>) -> StorageResult<Self> {
+        let root = root.into();
+        if !root.is_dir() {
+            return Err(StorageError::NotFound(root.display().to_string()));
+        }
+        Ok(Self {
+            root: fs::canonicalize(root)?,
+        })
+    }
+
+    /// Canonical path of the root directory
+    pub fn root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    fn local_path(&self, path: &str) -> StorageResult<PathBuf> {
+        let mut local = self.root.clone();
+        for name in split_path(path)? {
+            local.push(name);
+
+            let is_symlink = fs::symlink_metadata(&local)
+                .map(|x| x.file_type().is_symlink())
+                .unwrap_or(false);
+            if is_symlink {
+                match fs::canonicalize(&local) {
+                    Ok(target) if target.starts_with(&self.root) => {}
+                    _ => return Err(StorageError::InvalidPath(path.to_owned())),
+                }
+            }
+        }
+        Ok(local)
+    }
+
+    fn local_file_path(&self, file_path: &str) -> StorageResult<PathBuf> {
+        split_file_path(file_path)?;
+        self.local_path(file_path)
+    }
+
+    fn list(&self, path: &str, dirs: bool) -> StorageResult<Vec<String>> {
+        let local = self.local_path(path)?;
+        if !local.is_dir() {
+            return Err(StorageError::NotFound(path.to_owned()));
+        }
+        let mut names = vec![];
+        for entry in fs::read_dir(&local).map_err(|e| map_io_error(e, path))? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() == dirs {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+fn map_io_error(error: std::io::Error, path: &str) -> StorageError {
+    match error.kind() {
+        ErrorKind::NotFound => StorageError::NotFound(path.to_owned()),
+        ErrorKind::AlreadyExists => StorageError::AlreadyExists(path.to_owned()),
+        ErrorKind::StorageFull => StorageError::Full,
+        _ => StorageError::Io(error),
+    }
+}
+
+impl Storage for DirStorage {
+    fn dir_exist(&self, path: &str) -> StorageResult<bool> {
+        Ok(self.local_path(path)?.is_dir())
+    }
+
+    fn get_dir_list(&self, path: &str) -> StorageResult<Vec<String>> {
+        self.list(path, true)
+    }
+
+    fn create_dir(&self, path: &str) -> StorageResult<()> {
+        let local = self.local_file_path(path)?;
+        fs::create_dir(local).map_err(|e| map_io_error(e, path))
+    }
+
+    fn delete_dir(&self, path: &str) -> StorageResult<()> {
+        let local = self.local_file_path(path)?;
+        if !local.is_dir() {
+            return Err(StorageError::NotFound(path.to_owned()));
+        }
+        if fs::read_dir(&local)?.next().is_some() {
+            return Err(StorageError::DirectoryNotEmpty(path.to_owned()));
+        }
+        fs::remove_dir(local).map_err(|e| map_io_error(e, path))
+    }
+
+    fn file_exist(&self, file_path: &str) -> StorageResult<bool> {
+        Ok(self.local_path(file_path)?.is_file())
+    }
+
+    fn get_file_list(&self, path: &str) -> StorageResult<Vec<String>> {
+        self.list(path, false)
+    }
+
+    fn delete_file(&self, file_path: &str) -> StorageResult<()> {
+        let local = self.local_file_path(file_path)?;
+        if !local.is_file() {
+            return Err(StorageError::NotFound(file_path.to_owned()));
+        }
+        fs::remove_file(local).map_err(|e| map_io_error(e, file_path))
+    }
+
+    fn read_file(&self, file_path: &str) -> StorageResult<Vec<u8>> {
+        let local = self.local_file_path(file_path)?;
+        if !local.is_file() {
+            return Err(StorageError::NotFound(file_path.to_owned()));
+        }
+        fs::read(local).map_err(|e| map_io_error(e, file_path))
+    }
+
+    fn write_file(&self, file_path: &str, data: &[u8]) -> StorageResult<()> {
+        let local = self.local_file_path(file_path)?;
+        if local.is_dir() {
+            return Err(StorageError::AlreadyExists(file_path.to_owned()));
+        }
+        fs::write(local, data).map_err(|e| map_io_error(e, file_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Unique temporary directory removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .subsec_nanos();
+            let path = std::env::temp_dir().join(format!(
+                "dir_storage_test_{}_{}_{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::SeqCst),
+                nanos
+            ));
+            fs::create_dir(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn dir_storage_test() {
+        let root = TempDir::new();
+        let storage = DirStorage::new(&root.0).unwrap();
+
+        storage.create_dir("/logs").unwrap();
+        storage.write_file("/logs/1.log", b"started").unwrap();
+        storage.write_file("/readme.txt", b"hello").unwrap();
+
+        assert_eq!(storage.get_dir_list("/").unwrap(), vec!["logs"]);
+        assert_eq!(storage.get_file_list("/").unwrap(), vec!["readme.txt"]);
+        assert_eq!(storage.read_file("/logs/1.log").unwrap(), b"started");
+
+        assert!(matches!(
+            storage.create_dir("/logs"),
+            Err(StorageError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            storage.write_file("/config/a.cfg", b""),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.get_file_list("/readme.txt"),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.get_dir_list("/config"),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.read_file("/../outside"),
+            Err(StorageError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            storage.delete_dir("/logs"),
+            Err(StorageError::DirectoryNotEmpty(_))
+        ));
+
+        storage.delete_file("/logs/1.log").unwrap();
+        storage.delete_dir("/logs").unwrap();
+        storage.delete_file("/readme.txt").unwrap();
+        assert!(storage.get_dir_list("/").unwrap().is_empty());
+
+        assert!(matches!(
+            map_io_error(ErrorKind::StorageFull.into(), "/logs/2.log"),
+            StorageError::Full
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_test() {
+        use std::os::unix::fs::symlink;
+
+        let root = TempDir::new();
+        let outside = TempDir::new();
+        fs::write(outside.0.join("secret.txt"), b"secret").unwrap();
+        fs::create_dir(root.0.join("images")).unwrap();
+        fs::write(root.0.join("images/logo.bmp"), b"BM").unwrap();
+
+        symlink(&outside.0, root.0.join("escape")).unwrap();
+        symlink(outside.0.join("new.txt"), root.0.join("dangling.txt")).unwrap();
+        symlink(root.0.join("images"), root.0.join("pictures")).unwrap();
+
+        let storage = DirStorage::new(&root.0).unwrap();
+        assert!(matches!(
+            storage.read_file("/escape/secret.txt"),
+            Err(StorageError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            storage.get_file_list("/escape"),
+            Err(StorageError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            storage.write_file("/dangling.txt", b"data"),
+            Err(StorageError::InvalidPath(_))
+        ));
+        assert!(!outside.0.join("new.txt").exists());
+
+        assert_eq!(storage.read_file("/pictures/logo.bmp").unwrap(), b"BM");
+    }
+}
@@ -0,0 +1,203 @@
+use std::borrow::Cow;
+
+/// Standard message of EMV Contactless Book A, 9.4
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisplayMessage {
+    Approved,
+    NotAuthorised,
+    EnterPin,
+    ProcessingError,
+    RemoveCard,
+    Welcome,
+    PresentCard,
+    Processing,
+    CardReadOk,
+    InsertOrSwipeCard,
+    PresentOneCardOnly,
+    ApprovedPleaseSign,
+    AuthorisingPleaseWait,
+    TryAnotherCard,
+    InsertCard,
+    ClearDisplay,
+    SeePhone,
+    PresentCardAgain,
+    /// Message identifier without a standard meaning
+    Unknown(u8),
+    /// Text which is not a standard message
+    Text(String),
+}
+
+/// Message identifier, English and Russian text
+#[rustfmt::skip]
+const CATALOG: &[(u8, &str, &str)] = &[
+    (0x03, "Approved", "Одобрено"),
+    (0x07, "Not Authorised", "Отказано"),
+    (0x09, "Please Enter Your PIN", "Введите ПИН"),
+    (0x0F, "Processing Error", "Ошибка обработки"),
+    (0x10, "Remove Card", "Уберите карту"),
+    (0x14, "Welcome", "Добро пожаловать"),
+    (0x15, "Present Card", "Приложите карту"),
+    (0x16, "Processing", "Обработка"),
+    (0x17, "Card Read OK Please Remove Card", "Карта считана, уберите карту"),
+    (0x18, "Please Insert or Swipe Card", "Вставьте или проведите карту"),
+    (0x19, "Please Present One Card Only", "Приложите только одну карту"),
+    (0x1A, "Approved Please Sign", "Одобрено, распишитесь"),
+    (0x1B, "Authorising Please Wait", "Авторизация, подождите"),
+    (0x1C, "Insert, Swipe or Try Another Card", "Вставьте, проведите карту или используйте другую"),
+    (0x1D, "Please Insert Card", "Вставьте карту"),
+    (0x1E, "", ""),
+    (0x20, "See Phone for Instructions", "Следуйте инструкциям на телефоне"),
+    (0x21, "Present Card Again", "Приложите карту снова"),
+];
+
+impl DisplayMessage {
+    pub fn from_id(id: u8) -> Self {
+        match id {
+            0x03 => DisplayMessage::Approved,
+            0x07 => DisplayMessage::NotAuthorised,
+            0x09 => DisplayMessage::EnterPin,
+            0x0F => DisplayMessage::ProcessingError,
+            0x10 => DisplayMessage::RemoveCard,
+            0x14 => DisplayMessage::Welcome,
+            0x15 => DisplayMessage::PresentCard,
+            0x16 => DisplayMessage::Processing,
+            0x17 => DisplayMessage::CardReadOk,
+            0x18 => DisplayMessage::InsertOrSwipeCard,
+            0x19 => DisplayMessage::PresentOneCardOnly,
+            0x1A => DisplayMessage::ApprovedPleaseSign,
+            0x1B => DisplayMessage::AuthorisingPleaseWait,
+            0x1C => DisplayMessage::TryAnotherCard,
+            0x1D => DisplayMessage::InsertCard,
+            0x1E => DisplayMessage::ClearDisplay,
+            0x20 => DisplayMessage::SeePhone,
+            0x21 => DisplayMessage::PresentCardAgain,
+            _ => DisplayMessage::Unknown(id),
+        }
+    }
+
+    /// Recognizes English text of a standard message ignoring case and punctuation
+    pub fn from_text(text: &str) -> Self {
+        let normalize = |x: &str| {
+            x.chars()
+                .filter(|x| x.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        };
+        let text_key = normalize(text);
+
+        match CATALOG
+            .iter()
+            .find(|(_, english, _)| !english.is_empty() && normalize(english) == text_key)
+        {
+            Some((id, _, _)) => Self::from_id(*id),
+            None => DisplayMessage::Text(text.into()),
+        }
+    }
+
+    pub fn id(&self) -> Option<u8> {
+        match self {
+            DisplayMessage::Approved => Some(0x03),
+            DisplayMessage::NotAuthorised => Some(0x07),
+            DisplayMessage::EnterPin => Some(0x09),
+            DisplayMessage::ProcessingError => Some(0x0F),
+            DisplayMessage::RemoveCard => Some(0x10),
+            DisplayMessage::Welcome => Some(0x14),
+            DisplayMessage::PresentCard => Some(0x15),
+            DisplayMessage::Processing => Some(0x16),
+            DisplayMessage::CardReadOk => Some(0x17),
+            DisplayMessage::InsertOrSwipeCard => Some(0x18),
+            DisplayMessage::PresentOneCardOnly => Some(0x19),
+            DisplayMessage::ApprovedPleaseSign => Some(0x1A),
+            DisplayMessage::AuthorisingPleaseWait => Some(0x1B),
+            DisplayMessage::TryAnotherCard => Some(0x1C),
+            DisplayMessage::InsertCard => Some(0x1D),
+            DisplayMessage::ClearDisplay => Some(0x1E),
+            DisplayMessage::SeePhone => Some(0x20),
+            DisplayMessage::PresentCardAgain => Some(0x21),
+            DisplayMessage::Unknown(id) => Some(*id),
+            DisplayMessage::Text(_) => None,
+        }
+    }
+
+    /// Text to show, `Text` is returned as is
+    pub fn localized(&self, language: Language) -> Cow<'_, str> {
+        if let DisplayMessage::Text(text) = self {
+            return Cow::Borrowed(text);
+        }
+
+        let id = self.id();
+        match CATALOG.iter().find(|(x, _, _)| Some(*x) == id) {
+            Some((_, english, russian)) => Cow::Borrowed(match language {
+                Language::English => english,
+                Language::Russian => russian,
+            }),
+            None => Cow::Owned(format!("{:02X}", id.unwrap_or_default())),
+        }
+    }
+}
+
+/// Language of bundled translations
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Language {
+    English,
+    Russian,
+}
+
+impl Language {
+    /// ISO 639-1 code, e.g. `ru`
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.to_ascii_lowercase().as_str() {
+            "en" => Some(Language::English),
+            "ru" => Some(Language::Russian),
+            _ => None,
+        }
+    }
+
+    /// First supported language of Language Preference (5F2D), English if there is none
+    pub fn from_preference(preference: &[u8]) -> Self {
+        preference
+            .chunks_exact(2)
+            .filter_map(|x| std::str::from_utf8(x).ok())
+            .find_map(Self::from_code)
+            .unwrap_or(Language::English)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_message_test() {
+        for (id, _, _) in CATALOG {
+            assert_eq!(DisplayMessage::from_id(*id).id(), Some(*id));
+        }
+        assert_eq!(DisplayMessage::from_id(0x01), DisplayMessage::Unknown(0x01));
+
+        assert_eq!(
+            DisplayMessage::from_text("NOT AUTHORISED"),
+            DisplayMessage::NotAuthorised
+        );
+        assert_eq!(
+            DisplayMessage::from_text("Authorising, please wait"),
+            DisplayMessage::AuthorisingPleaseWait
+        );
+        assert_eq!(
+            DisplayMessage::from_text("Balance: 100"),
+            DisplayMessage::Text("Balance: 100".into())
+        );
+
+        let russian = Language::from_preference(b"deruen");
+        assert_eq!(russian, Language::Russian);
+        assert_eq!(Language::from_preference(b"de"), Language::English);
+        assert_eq!(
+            DisplayMessage::PresentCard.localized(russian),
+            "Приложите карту"
+        );
+        assert_eq!(
+            DisplayMessage::SeePhone.localized(Language::English),
+            "See Phone for Instructions"
+        );
+        assert_eq!(DisplayMessage::Unknown(0x30).localized(russian), "30");
+    }
+}
@@ -15,6 +15,14 @@ pub enum DeviceError {
     MessageChannel(String),
     #[error("TLV content error: {0}")]
     TlvContent(String, Tlv),
+    #[error("APDU error: {0}")]
+    Apdu(#[from] ApduError),
+    #[error("card returned status {0:04X}")]
+    CardStatus(u16),
+    #[error("configuration error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("CAPK error: {0}")]
+    Capk(#[from] CapkError),
     #[error("{0}")]
     Other(String),
 }
@@ -25,8 +33,70 @@ impl From<TlvError> for DeviceError {
     }
 }
 
+#[derive(Error, Debug)]
+pub enum ApduError {
+    #[error("malformed APDU of {0} bytes")]
+    Malformed(usize),
+    #[error("command data too long: {0} bytes")]
+    DataTooLong(usize),
+    #[error("invalid expected length: {0}")]
+    InvalidLe(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("tag {0:X} is not a configuration parameter")]
+    UnknownTag(usize),
+    #[error("tag {tag:X} ({name}) has invalid length {len}")]
+    InvalidLength {
+        tag: usize,
+        name: &'static str,
+        len: usize,
+    },
+    #[error("tag {tag:X} ({name}) has invalid value: {value:02X?}")]
+    InvalidFormat {
+        tag: usize,
+        name: &'static str,
+        value: Vec<u8>,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum CapkError {
+    #[error("malformed CAPK file at line {line}: {message}")]
+    Malformed { line: usize, message: String },
+    #[error("checksum mismatch of CAPK {0}")]
+    ChecksumMismatch(String),
+}
+
 #[derive(Error, Debug)]
 pub enum StorageError {
-    #[error("device error")]
+    #[error("invalid path: {0}")]
+    InvalidPath(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("already exists: {0}")]
+    AlreadyExists(String),
+    #[error("directory is not empty: {0}")]
+    DirectoryNotEmpty(String),
+    #[error("storage is full")]
+    Full,
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+}
+
+#[derive(Error, Debug)]
+pub enum NdefError {
+    #[error("malformed NDEF: {0}")]
+    Malformed(String),
+    #[error("tag is not NDEF formatted: {0}")]
+    NotFormatted(String),
+    #[error("tag is read only")]
+    ReadOnly,
+    #[error("NDEF message of {size} bytes exceeds tag capacity of {capacity} bytes")]
+    TooLarge { size: usize, capacity: usize },
+    #[error("device error: {0}")]
     Device(#[from] DeviceError),
 }
@@ -0,0 +1,131 @@
+use crate::display_message;
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use display_message::DisplayMessage;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceEvent {
+    /// Message the reader asks to show to the cardholder
+    DisplayMessage(DisplayMessage),
+    /// Diagnostic line produced by the reader firmware
+    InternalLog(String),
+    CardRemoved,
+    /// Language Preference (5F2D) of the card, published as soon as the reader reports it
+    /// so that following display messages can be localized
+    LanguagePreference(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceEventRecord {
+    pub timestamp: SystemTime,
+    pub event: DeviceEvent,
+}
+
+/// Delivers device events to any number of subscribers
+///
+/// Every subscriber has its own unbounded queue, so a slow subscriber
+/// never blocks the publisher.
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<DeviceEventRecord>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            subscribers: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn subscribe(&self) -> EventSubscription {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        EventSubscription { events: rx }
+    }
+
+    pub fn publish(&self, event: DeviceEvent) {
+        let record = DeviceEventRecord {
+            timestamp: SystemTime::now(),
+            event,
+        };
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|x| x.send(record.clone()).is_ok());
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Receiving side of `EventBus`, iteration ends when the device is dropped
+pub struct EventSubscription {
+    events: Receiver<DeviceEventRecord>,
+}
+
+impl EventSubscription {
+    /// Subscription without events, for devices which do not report any
+    pub fn closed() -> Self {
+        let (_, rx) = mpsc::channel();
+        Self { events: rx }
+    }
+
+    /// Returns next event if there is one
+    pub fn try_next(&self) -> Option<DeviceEventRecord> {
+        match self.events.try_recv() {
+            Ok(o) => Some(o),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Waits for next event at most `timeout`
+    pub fn next_timeout(&self, timeout: Duration) -> Option<DeviceEventRecord> {
+        match self.events.recv_timeout(timeout) {
+            Ok(o) => Some(o),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+}
+
+impl Iterator for EventSubscription {
+    type Item = DeviceEventRecord;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_bus_test() {
+        let bus = EventBus::new();
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+
+        bus.publish(DeviceEvent::DisplayMessage(DisplayMessage::PresentCard));
+        drop(second);
+        bus.publish(DeviceEvent::CardRemoved);
+        drop(bus);
+
+        let events: Vec<DeviceEvent> = first.map(|x| x.event).collect();
+        assert_eq!(
+            events,
+            vec![
+                DeviceEvent::DisplayMessage(DisplayMessage::PresentCard),
+                DeviceEvent::CardRemoved
+            ]
+        );
+
+        let mut closed = EventSubscription::closed();
+        assert!(closed.try_next().is_none());
+        assert!(closed.next().is_none());
+    }
+}
@@ -1,6 +1,25 @@
+pub mod apdu;
+pub mod async_device;
+pub mod capk;
+pub mod card;
+pub mod clock;
+pub mod config;
 pub mod device;
+pub mod device_info;
+pub mod display_message;
+pub mod mifare;
+pub mod ndef;
+pub mod ndef_tag;
+#[cfg(test)]
+mod simulated_card;
 
 pub mod error;
+pub mod event;
+pub mod outcome;
+
+pub mod dir_storage;
+pub mod memory_storage;
+pub mod storage;
 
 pub mod tag_value;
 pub mod tlv_parser;
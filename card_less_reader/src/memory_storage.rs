@@ -0,0 +1,285 @@
+use crate::error;
+use crate::storage;
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use error::StorageError;
+use storage::{split_file_path, split_path, Storage, StorageResult};
+
+enum Node {
+    Dir,
+    File(Vec<u8>),
+}
+
+/// `Storage` kept in memory, useful for tests and as a staging area
+pub struct MemoryStorage {
+    nodes: Mutex<BTreeMap<String, Node>>,
+    capacity: Option<usize>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self {
+            nodes: Mutex::new(BTreeMap::new()),
+            capacity: None,
+        }
+    }
+
+    /// Creates storage which returns `StorageError::Full` when total size of files exceeds `capacity` bytes
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            nodes: Mutex::new(BTreeMap::new()),
+            capacity: Some(capacity),
+        }
+    }
+
+    fn key(names: &[&str]) -> String {
+        names.join("/")
+    }
+
+    /// Returns keys of the parent directory and of the node itself
+    fn node_key(path: &str) -> StorageResult<(String, String)> {
+        let (parent, name) = split_file_path(path)?;
+        let parent = Self::key(&parent);
+        let key = if parent.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}/{}", parent, name)
+        };
+        Ok((parent, key))
+    }
+
+    fn is_dir(nodes: &BTreeMap<String, Node>, key: &str) -> bool {
+        key.is_empty() || matches!(nodes.get(key), Some(Node::Dir))
+    }
+
+    fn children<'a>(
+        nodes: &'a BTreeMap<String, Node>,
+        key: &str,
+    ) -> impl Iterator<Item = (&'a str, &'a Node)> {
+        let prefix = if key.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", key)
+        };
+        nodes
+            .iter()
+            .filter(move |(k, _)| k.starts_with(&prefix) && !k[prefix.len()..].contains('/'))
+            .map(move |(k, v)| (&k[k.rfind('/').map(|x| x + 1).unwrap_or(0)..], v))
+    }
+
+    fn list(&self, path: &str, dirs: bool) -> StorageResult<Vec<String>> {
+        let key = Self::key(&split_path(path)?);
+        let nodes = self.nodes.lock().unwrap();
+        if !Self::is_dir(&nodes, &key) {
+            return Err(StorageError::NotFound(path.to_owned()));
+        }
+        Ok(Self::children(&nodes, &key)
+            .filter(|(_, v)| matches!(v, Node::Dir) == dirs)
+            .map(|(k, _)| k.to_owned())
+            .collect())
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn dir_exist(&self, path: &str) -> StorageResult<bool> {
+        let key = Self::key(&split_path(path)?);
+        Ok(Self::is_dir(&self.nodes.lock().unwrap(), &key))
+    }
+
+    fn get_dir_list(&self, path: &str) -> StorageResult<Vec<String>> {
+        self.list(path, true)
+    }
+
+    fn create_dir(&self, path: &str) -> StorageResult<()> {
+        let (parent, key) = Self::node_key(path)?;
+
+        let mut nodes = self.nodes.lock().unwrap();
+        if !Self::is_dir(&nodes, &parent) {
+            return Err(StorageError::NotFound(path.to_owned()));
+        }
+        if nodes.contains_key(&key) {
+            return Err(StorageError::AlreadyExists(path.to_owned()));
+        }
+        nodes.insert(key, Node::Dir);
+        Ok(())
+    }
+
+    fn delete_dir(&self, path: &str) -> StorageResult<()> {
+        let (_, key) = Self::node_key(path)?;
+
+        let mut nodes = self.nodes.lock().unwrap();
+        if !matches!(nodes.get(&key), Some(Node::Dir)) {
+            return Err(StorageError::NotFound(path.to_owned()));
+        }
+        if Self::children(&nodes, &key).next().is_some() {
+            return Err(StorageError::DirectoryNotEmpty(path.to_owned()));
+        }
+        nodes.remove(&key);
+        Ok(())
+    }
+
+    fn file_exist(&self, file_path: &str) -> StorageResult<bool> {
+        let key = Self::key(&split_path(file_path)?);
+        Ok(matches!(
+            self.nodes.lock().unwrap().get(&key),
+            Some(Node::File(_))
+        ))
+    }
+
+    fn get_file_list(&self, path: &str) -> StorageResult<Vec<String>> {
+        self.list(path, false)
+    }
+
+    fn delete_file(&self, file_path: &str) -> StorageResult<()> {
+        let (_, key) = Self::node_key(file_path)?;
+
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get(&key) {
+            Some(Node::File(_)) => {
+                nodes.remove(&key);
+                Ok(())
+            }
+            _ => Err(StorageError::NotFound(file_path.to_owned())),
+        }
+    }
+
+    fn read_file(&self, file_path: &str) -> StorageResult<Vec<u8>> {
+        let (_, key) = Self::node_key(file_path)?;
+
+        match self.nodes.lock().unwrap().get(&key) {
+            Some(Node::File(data)) => Ok(data.clone()),
+            _ => Err(StorageError::NotFound(file_path.to_owned())),
+        }
+    }
+
+    fn write_file(&self, file_path: &str, data: &[u8]) -> StorageResult<()> {
+        let (parent, key) = Self::node_key(file_path)?;
+
+        let mut nodes = self.nodes.lock().unwrap();
+        if !Self::is_dir(&nodes, &parent) {
+            return Err(StorageError::NotFound(file_path.to_owned()));
+        }
+
+        let size = match nodes.get(&key) {
+            Some(Node::Dir) => return Err(StorageError::AlreadyExists(file_path.to_owned())),
+            Some(Node::File(old)) => old.len(),
+            None => 0,
+        };
+
+        if let Some(capacity) = self.capacity {
+            let used: usize = nodes
+                .values()
+                .map(|x| match x {
+                    Node::File(data) => data.len(),
+                    Node::Dir => 0,
+                })
+                .sum();
+            if used - size + data.len() > capacity {
+                return Err(StorageError::Full);
+            }
+        }
+
+        nodes.insert(key, Node::File(data.to_vec()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+
+    #[test]
+    fn dir_test() {
+        let storage = MemoryStorage::new();
+
+        storage.create_dir("/images").unwrap();
+        storage.create_dir("/images/idle").unwrap();
+        storage.create_dir("/logs").unwrap();
+
+        assert!(storage.dir_exist("/").unwrap());
+        assert!(storage.dir_exist("/images/idle").unwrap());
+        assert!(!storage.dir_exist("/config").unwrap());
+        assert_eq!(storage.get_dir_list("/").unwrap(), vec!["images", "logs"]);
+        assert_eq!(storage.get_dir_list("/images").unwrap(), vec!["idle"]);
+
+        assert!(matches!(
+            storage.create_dir("/images"),
+            Err(StorageError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            storage.create_dir("/config/kernels"),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.delete_dir("/images"),
+            Err(StorageError::DirectoryNotEmpty(_))
+        ));
+
+        storage.delete_dir("/images/idle").unwrap();
+        storage.delete_dir("/images").unwrap();
+        assert_eq!(storage.get_dir_list("/").unwrap(), vec!["logs"]);
+    }
+
+    #[test]
+    fn file_test() {
+        let storage = MemoryStorage::new();
+        storage.create_dir("/config").unwrap();
+
+        storage.write_file("/config/terminal.cfg", b"9F1A=0643").unwrap();
+        storage.write_file("/readme.txt", b"hello").unwrap();
+
+        assert!(storage.file_exist("/config/terminal.cfg").unwrap());
+        assert!(!storage.file_exist("/config").unwrap());
+        assert_eq!(storage.get_file_list("/").unwrap(), vec!["readme.txt"]);
+        assert_eq!(
+            storage.read_file("/config/terminal.cfg").unwrap(),
+            b"9F1A=0643"
+        );
+
+        let mut buf = String::new();
+        storage
+            .open_read_file("/readme.txt")
+            .unwrap()
+            .read_to_string(&mut buf)
+            .unwrap();
+        assert_eq!(buf, "hello");
+
+        assert!(matches!(
+            storage.write_file("/logs/1.log", b""),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.read_file("/missing"),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.write_file("/", b""),
+            Err(StorageError::InvalidPath(_))
+        ));
+
+        storage.delete_file("/readme.txt").unwrap();
+        assert!(!storage.file_exist("/readme.txt").unwrap());
+    }
+
+    #[test]
+    fn capacity_test() {
+        let storage = MemoryStorage::with_capacity(8);
+
+        storage.write_file("/a", &[0; 6]).unwrap();
+        storage.write_file("/a", &[0; 8]).unwrap();
+        assert!(matches!(
+            storage.write_file("/b", &[0; 1]),
+            Err(StorageError::Full)
+        ));
+    }
+}
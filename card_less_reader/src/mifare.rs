@@ -0,0 +1,345 @@
+use crate::apdu;
+use crate::card;
+use crate::error;
+
+use apdu::{CommandApdu, ResponseApdu};
+use card::CardAccess;
+use error::DeviceError;
+
+pub const BLOCK_SIZE: usize = 16;
+pub const PAGE_SIZE: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyType {
+    A,
+    B,
+}
+
+/// MIFARE Classic 1K/4K and Mini
+pub trait MifareClassic {
+    /// Authenticates the sector of `block`
+    fn authenticate(&self, block: u8, key_type: KeyType, key: &[u8; 6]) -> Result<(), DeviceError>;
+
+    fn read_block(&self, block: u8) -> Result<[u8; BLOCK_SIZE], DeviceError>;
+
+    fn write_block(&self, block: u8, data: &[u8; BLOCK_SIZE]) -> Result<(), DeviceError>;
+
+    /// Adds `delta` to the value block
+    fn increment(&self, block: u8, delta: u32) -> Result<(), DeviceError>;
+
+    /// Subtracts `delta` from the value block
+    fn decrement(&self, block: u8, delta: u32) -> Result<(), DeviceError>;
+
+    /// Copies the value block `source` to `target` of the same sector
+    fn restore(&self, source: u8, target: u8) -> Result<(), DeviceError>;
+
+    fn read_value(&self, block: u8) -> Result<i32, DeviceError> {
+        match decode_value_block(&self.read_block(block)?) {
+            Some((value, _)) => Ok(value),
+            None => Err(DeviceError::Other(format!(
+                "block {} is not a value block",
+                block
+            ))),
+        }
+    }
+
+    /// Formats `block` as a value block
+    fn write_value(&self, block: u8, value: i32) -> Result<(), DeviceError> {
+        self.write_block(block, &encode_value_block(value, block))
+    }
+}
+
+/// MIFARE Ultralight and NTAG
+pub trait MifareUltralight {
+    /// Reads 4 pages starting from `page`
+    fn read_pages(&self, page: u8) -> Result<[u8; BLOCK_SIZE], DeviceError>;
+
+    fn write_page(&self, page: u8, data: &[u8; PAGE_SIZE]) -> Result<(), DeviceError>;
+}
+
+/// Memory cards over PC/SC pseudo-APDUs (PC/SC Part 3) sent through `CardAccess`
+///
+/// The pseudo-APDUs are handled by the reader itself, so `CardAccess` must belong to a PC/SC
+/// reader such as `pcsc_nfc_reader::reader::PcscReader`, a reader forwarding APDUs to
+/// ISO/IEC 14443-4 cards can not access memory cards.
+/// Value block commands use the common `FF D7` extension.
+pub struct PcscMemoryCard<'a> {
+    card: &'a dyn CardAccess,
+}
+
+/// Key slot of the reader used for authentication
+const KEY_NUMBER: u8 = 0x00;
+
+impl<'a> PcscMemoryCard<'a> {
+    pub fn new(card: &'a dyn CardAccess) -> Self {
+        Self { card }
+    }
+
+    fn transceive(&self, command: CommandApdu) -> Result<ResponseApdu, DeviceError> {
+        let response = self.card.transceive(&command)?;
+        if !response.is_success() {
+            return Err(DeviceError::CardStatus(response.sw));
+        }
+        Ok(response)
+    }
+
+    fn read_binary(&self, address: u8) -> Result<[u8; BLOCK_SIZE], DeviceError> {
+        let response =
+            self.transceive(CommandApdu::new(0xFF, 0xB0, 0x00, address).with_le(BLOCK_SIZE))?;
+
+        let mut data = [0; BLOCK_SIZE];
+        if response.data.len() != BLOCK_SIZE {
+            return Err(DeviceError::Other(format!(
+                "expected {} bytes, card returned {}",
+                BLOCK_SIZE,
+                response.data.len()
+            )));
+        }
+        data.copy_from_slice(&response.data);
+        Ok(data)
+    }
+
+    fn update_binary(&self, address: u8, data: &[u8]) -> Result<(), DeviceError> {
+        self.transceive(CommandApdu::new(0xFF, 0xD6, 0x00, address).with_data(data.to_vec()))?;
+        Ok(())
+    }
+
+    fn value_operation(&self, block: u8, operation: u8, value: u32) -> Result<(), DeviceError> {
+        let mut data = vec![operation];
+        data.extend_from_slice(&value.to_be_bytes());
+        self.transceive(CommandApdu::new(0xFF, 0xD7, 0x00, block).with_data(data))?;
+        Ok(())
+    }
+}
+
+impl MifareClassic for PcscMemoryCard<'_> {
+    fn authenticate(&self, block: u8, key_type: KeyType, key: &[u8; 6]) -> Result<(), DeviceError> {
+        self.transceive(CommandApdu::new(0xFF, 0x82, 0x00, KEY_NUMBER).with_data(key.to_vec()))?;
+
+        let key_type = match key_type {
+            KeyType::A => 0x60,
+            KeyType::B => 0x61,
+        };
+        self.transceive(
+            CommandApdu::new(0xFF, 0x86, 0x00, 0x00)
+                .with_data(vec![0x01, 0x00, block, key_type, KEY_NUMBER]),
+        )?;
+        Ok(())
+    }
+
+    fn read_block(&self, block: u8) -> Result<[u8; BLOCK_SIZE], DeviceError> {
+        self.read_binary(block)
+    }
+
+    fn write_block(&self, block: u8, data: &[u8; BLOCK_SIZE]) -> Result<(), DeviceError> {
+        self.update_binary(block, data)
+    }
+
+    fn increment(&self, block: u8, delta: u32) -> Result<(), DeviceError> {
+        self.value_operation(block, 0x01, delta)
+    }
+
+    fn decrement(&self, block: u8, delta: u32) -> Result<(), DeviceError> {
+        self.value_operation(block, 0x02, delta)
+    }
+
+    fn restore(&self, source: u8, target: u8) -> Result<(), DeviceError> {
+        self.transceive(CommandApdu::new(0xFF, 0xD7, 0x00, source).with_data(vec![0x03, target]))?;
+        Ok(())
+    }
+}
+
+impl MifareUltralight for PcscMemoryCard<'_> {
+    fn read_pages(&self, page: u8) -> Result<[u8; BLOCK_SIZE], DeviceError> {
+        self.read_binary(page)
+    }
+
+    fn write_page(&self, page: u8, data: &[u8; PAGE_SIZE]) -> Result<(), DeviceError> {
+        self.update_binary(page, data)
+    }
+}
+
+/// Returns the trailer block of the sector containing `block`
+pub fn sector_trailer(block: u8) -> u8 {
+    if block < 128 {
+        block | 0x03
+    } else {
+        block | 0x0F
+    }
+}
+
+pub fn encode_value_block(value: i32, address: u8) -> [u8; BLOCK_SIZE] {
+    let value = value.to_le_bytes();
+    let inverted = (!i32::from_le_bytes(value)).to_le_bytes();
+
+    let mut block = [0; BLOCK_SIZE];
+    block[0..4].copy_from_slice(&value);
+    block[4..8].copy_from_slice(&inverted);
+    block[8..12].copy_from_slice(&value);
+    block[12] = address;
+    block[13] = !address;
+    block[14] = address;
+    block[15] = !address;
+    block
+}
+
+/// Returns value and address of the value block, `None` if the block is not a valid value block
+pub fn decode_value_block(block: &[u8; BLOCK_SIZE]) -> Option<(i32, u8)> {
+    let value = i32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+    let inverted = i32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let copy = i32::from_le_bytes([block[8], block[9], block[10], block[11]]);
+    let address = block[12];
+
+    if value != copy
+        || value != !inverted
+        || block[13] != !address
+        || block[14] != address
+        || block[15] != !address
+    {
+        return None;
+    }
+    Some((value, address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulated_card::SimulatedCard;
+
+    use crate::card::{CardType, PollCardResult};
+    use crate::device::CancellationToken;
+
+    use std::sync::Mutex;
+
+    const DEFAULT_KEY: [u8; 6] = [0xFF; 6];
+
+    #[test]
+    fn value_block_test() {
+        let block = encode_value_block(-5, 4);
+        assert_eq!(decode_value_block(&block), Some((-5, 4)));
+
+        let mut broken = block;
+        broken[5] ^= 0x01;
+        assert_eq!(decode_value_block(&broken), None);
+
+        assert_eq!(sector_trailer(5), 7);
+        assert_eq!(sector_trailer(130), 143);
+    }
+
+    #[test]
+    fn mifare_classic_test() {
+        let card = SimulatedCard::mifare_classic_1k(vec![0x01, 0x02, 0x03, 0x04]);
+        card.activate(&CancellationToken::new()).unwrap();
+        let classic = PcscMemoryCard::new(&card);
+
+        assert!(matches!(
+            classic.read_block(4),
+            Err(DeviceError::CardStatus(_))
+        ));
+        assert!(classic.authenticate(4, KeyType::A, &[0x00; 6]).is_err());
+
+        classic.authenticate(4, KeyType::A, &DEFAULT_KEY).unwrap();
+        classic.write_block(4, &[0x11; BLOCK_SIZE]).unwrap();
+        assert_eq!(classic.read_block(4).unwrap(), [0x11; BLOCK_SIZE]);
+        assert_eq!(card.memory()[4 * BLOCK_SIZE..5 * BLOCK_SIZE], [0x11; BLOCK_SIZE]);
+
+        classic.write_value(5, 100).unwrap();
+        classic.increment(5, 20).unwrap();
+        classic.decrement(5, 50).unwrap();
+        assert_eq!(classic.read_value(5).unwrap(), 70);
+
+        classic.restore(5, 6).unwrap();
+        assert_eq!(classic.read_value(6).unwrap(), 70);
+        assert!(classic.read_value(4).is_err());
+
+        // Another sector is not authenticated
+        assert!(classic.read_block(8).is_err());
+    }
+
+    #[test]
+    fn mifare_classic_4k_test() {
+        let card = SimulatedCard::mifare_classic_4k(vec![0x01, 0x02, 0x03, 0x04]);
+        match card.activate(&CancellationToken::new()).unwrap() {
+            PollCardResult::Card(info) => assert_eq!(info.card_type, CardType::MifareClassic4K),
+            PollCardResult::Canceled => panic!("card is not activated"),
+        }
+        let classic = PcscMemoryCard::new(&card);
+
+        // Sectors above 2K have 16 blocks
+        classic.authenticate(200, KeyType::B, &DEFAULT_KEY).unwrap();
+        classic.write_block(200, &[0x22; BLOCK_SIZE]).unwrap();
+        assert_eq!(classic.read_block(195).unwrap(), [0x00; BLOCK_SIZE]);
+        assert_eq!(classic.read_block(200).unwrap(), [0x22; BLOCK_SIZE]);
+    }
+
+    #[test]
+    fn mifare_ultralight_test() {
+        let card =
+            SimulatedCard::mifare_ultralight(vec![0x04, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06], 16);
+        card.activate(&CancellationToken::new()).unwrap();
+        let ultralight = PcscMemoryCard::new(&card);
+
+        ultralight.write_page(4, &[1, 2, 3, 4]).unwrap();
+        ultralight.write_page(5, &[5, 6, 7, 8]).unwrap();
+
+        let pages = ultralight.read_pages(4).unwrap();
+        assert_eq!(&pages[..8], &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // Reading wraps around the end of memory
+        let pages = ultralight.read_pages(15).unwrap();
+        assert_eq!(&pages[4..8], &[0x04, 0x01, 0x02, 0x88 ^ 0x04 ^ 0x01 ^ 0x02]);
+    }
+
+    /// Records commands and answers each of them with 16 bytes of data
+    struct RecordingReader {
+        commands: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl CardAccess for RecordingReader {
+        fn activate(&self, _cancel: &CancellationToken) -> Result<PollCardResult, DeviceError> {
+            Ok(PollCardResult::Canceled)
+        }
+
+        fn transceive(&self, command: &CommandApdu) -> Result<ResponseApdu, DeviceError> {
+            self.commands.lock().unwrap().push(command.to_vec()?);
+            Ok(ResponseApdu::new(vec![0x00; BLOCK_SIZE], 0x9000))
+        }
+
+        fn deactivate(&self) -> Result<(), DeviceError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pcsc_commands_test() {
+        let reader = RecordingReader {
+            commands: Mutex::new(vec![]),
+        };
+        let classic = PcscMemoryCard::new(&reader);
+
+        classic.authenticate(4, KeyType::A, &DEFAULT_KEY).unwrap();
+        classic.read_block(4).unwrap();
+        classic.write_block(4, &[0x11; BLOCK_SIZE]).unwrap();
+        classic.increment(5, 20).unwrap();
+        classic.restore(5, 6).unwrap();
+        classic.write_page(7, &[1, 2, 3, 4]).unwrap();
+
+        let mut update_binary = vec![0xFF, 0xD6, 0x00, 0x04, 0x10];
+        update_binary.extend_from_slice(&[0x11; BLOCK_SIZE]);
+        assert_eq!(
+            *reader.commands.lock().unwrap(),
+            vec![
+                // Load Keys into the volatile key slot 0
+                vec![0xFF, 0x82, 0x00, 0x00, 0x06, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+                // General Authenticate of block 4 with key A of slot 0
+                vec![0xFF, 0x86, 0x00, 0x00, 0x05, 0x01, 0x00, 0x04, 0x60, 0x00],
+                // Read Binary
+                vec![0xFF, 0xB0, 0x00, 0x04, 0x10],
+                update_binary,
+                vec![0xFF, 0xD7, 0x00, 0x05, 0x05, 0x01, 0x00, 0x00, 0x00, 0x14],
+                vec![0xFF, 0xD7, 0x00, 0x05, 0x02, 0x03, 0x06],
+                vec![0xFF, 0xD6, 0x00, 0x07, 0x04, 0x01, 0x02, 0x03, 0x04],
+            ]
+        );
+    }
+}
@@ -0,0 +1,531 @@
+use crate::error;
+
+use error::NdefError;
+
+const MB: u8 = 0x80;
+const ME: u8 = 0x40;
+const CF: u8 = 0x20;
+const SR: u8 = 0x10;
+const IL: u8 = 0x08;
+
+/// Type Name Format of a record
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tnf {
+    Empty,
+    WellKnown,
+    Media,
+    AbsoluteUri,
+    External,
+    Unknown,
+    Unchanged,
+    Reserved,
+}
+
+impl Tnf {
+    pub fn code(&self) -> u8 {
+        match self {
+            Tnf::Empty => 0x00,
+            Tnf::WellKnown => 0x01,
+            Tnf::Media => 0x02,
+            Tnf::AbsoluteUri => 0x03,
+            Tnf::External => 0x04,
+            Tnf::Unknown => 0x05,
+            Tnf::Unchanged => 0x06,
+            Tnf::Reserved => 0x07,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Self {
+        match code & 0x07 {
+            0x00 => Tnf::Empty,
+            0x01 => Tnf::WellKnown,
+            0x02 => Tnf::Media,
+            0x03 => Tnf::AbsoluteUri,
+            0x04 => Tnf::External,
+            0x05 => Tnf::Unknown,
+            0x06 => Tnf::Unchanged,
+            _ => Tnf::Reserved,
+        }
+    }
+}
+
+/// URI identifier codes of the URI record type definition
+const URI_PREFIXES: [&str; 36] = [
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+/// Decoded payload of the common record types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NdefContent {
+    Uri(String),
+    Text {
+        language: String,
+        text: String,
+    },
+    SmartPoster {
+        uri: String,
+        /// Language and text of the titles
+        titles: Vec<(String, String)>,
+    },
+    Mime {
+        mime_type: String,
+        data: Vec<u8>,
+    },
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NdefRecord {
+    pub tnf: Tnf,
+    pub record_type: Vec<u8>,
+    pub id: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl NdefRecord {
+    pub fn new(tnf: Tnf, record_type: &[u8], payload: Vec<u8>) -> Self {
+        Self {
+            tnf,
+            record_type: record_type.to_vec(),
+            id: vec![],
+            payload,
+        }
+    }
+
+    pub fn with_id(mut self, id: Vec<u8>) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn uri(uri: &str) -> Self {
+        let (code, prefix) = URI_PREFIXES
+            .iter()
+            .enumerate()
+            .filter(|(_, prefix)| uri.starts_with(*prefix))
+            .max_by_key(|(_, prefix)| prefix.len())
+            .unwrap_or((0, &""));
+
+        let mut payload = vec![code as u8];
+        payload.extend_from_slice(&uri.as_bytes()[prefix.len()..]);
+        Self::new(Tnf::WellKnown, b"U", payload)
+    }
+
+    /// Text record in UTF-8, `language` is an IANA language code like `en`
+    pub fn text(language: &str, text: &str) -> Self {
+        let language = &language.as_bytes()[..language.len().min(0x3F)];
+        let mut payload = vec![language.len() as u8];
+        payload.extend_from_slice(language);
+        payload.extend_from_slice(text.as_bytes());
+        Self::new(Tnf::WellKnown, b"T", payload)
+    }
+
+    /// Smart poster with `uri` and titles given as language and text
+    pub fn smart_poster(uri: &str, titles: &[(&str, &str)]) -> Self {
+        let mut records = vec![Self::uri(uri)];
+        for (language, text) in titles {
+            records.push(Self::text(language, text));
+        }
+        let payload = NdefMessage::new(records)
+            .to_vec()
+            .expect("URI and text records have short type and no id");
+        Self::new(Tnf::WellKnown, b"Sp", payload)
+    }
+
+    /// MIME record, e.g. `text/vcard`
+    pub fn mime(mime_type: &str, data: Vec<u8>) -> Self {
+        Self::new(Tnf::Media, mime_type.as_bytes(), data)
+    }
+
+    pub fn content(&self) -> Result<NdefContent, NdefError> {
+        match (self.tnf, self.record_type.as_slice()) {
+            (Tnf::WellKnown, b"U") => Ok(NdefContent::Uri(decode_uri(&self.payload)?)),
+            (Tnf::WellKnown, b"T") => {
+                let (language, text) = decode_text(&self.payload)?;
+                Ok(NdefContent::Text { language, text })
+            }
+            (Tnf::WellKnown, b"Sp") => {
+                let message = NdefMessage::from_bytes(&self.payload)?;
+                let mut uri = None;
+                let mut titles = vec![];
+                for record in message.records.iter() {
+                    match record.content()? {
+                        NdefContent::Uri(x) if uri.is_none() => uri = Some(x),
+                        NdefContent::Text { language, text } => titles.push((language, text)),
+                        _ => {}
+                    }
+                }
+                match uri {
+                    Some(uri) => Ok(NdefContent::SmartPoster { uri, titles }),
+                    None => Err(NdefError::Malformed("smart poster without URI".into())),
+                }
+            }
+            (Tnf::Media, mime_type) => Ok(NdefContent::Mime {
+                mime_type: String::from_utf8_lossy(mime_type).into_owned(),
+                data: self.payload.clone(),
+            }),
+            (Tnf::AbsoluteUri, uri) => {
+                Ok(NdefContent::Uri(String::from_utf8_lossy(uri).into_owned()))
+            }
+            _ => Ok(NdefContent::Other),
+        }
+    }
+}
+
+fn decode_uri(payload: &[u8]) -> Result<String, NdefError> {
+    let (code, rest) = match payload.split_first() {
+        Some(o) => o,
+        None => return Err(NdefError::Malformed("empty URI record".into())),
+    };
+    let prefix = URI_PREFIXES.get(*code as usize).unwrap_or(&"");
+    match std::str::from_utf8(rest) {
+        Ok(rest) => Ok(format!("{}{}", prefix, rest)),
+        Err(_) => Err(NdefError::Malformed("URI is not UTF-8".into())),
+    }
+}
+
+fn decode_text(payload: &[u8]) -> Result<(String, String), NdefError> {
+    let status = match payload.first() {
+        Some(o) => *o,
+        None => return Err(NdefError::Malformed("empty text record".into())),
+    };
+    let language_len = (status & 0x3F) as usize;
+    if payload.len() < 1 + language_len {
+        return Err(NdefError::Malformed(
+            "text record language is too long".into(),
+        ));
+    }
+
+    let language = String::from_utf8_lossy(&payload[1..1 + language_len]).into_owned();
+    let encoded = &payload[1 + language_len..];
+    let text = if status & 0x80 == 0 {
+        std::str::from_utf8(encoded)
+            .map_err(|_| NdefError::Malformed("text is not UTF-8".into()))?
+            .to_owned()
+    } else {
+        decode_utf16(encoded)?
+    };
+
+    Ok((language, text))
+}
+
+/// Decodes UTF-16 text, big endian unless there is a byte order mark
+fn decode_utf16(encoded: &[u8]) -> Result<String, NdefError> {
+    let (little_endian, encoded) = match encoded {
+        [0xFF, 0xFE, rest @ ..] => (true, rest),
+        [0xFE, 0xFF, rest @ ..] => (false, rest),
+        _ => (false, encoded),
+    };
+    let chunks = encoded.chunks_exact(2);
+    if !chunks.remainder().is_empty() {
+        return Err(NdefError::Malformed("odd length of UTF-16 text".into()));
+    }
+    let units: Vec<u16> = chunks
+        .map(|x| match little_endian {
+            true => u16::from_le_bytes([x[0], x[1]]),
+            false => u16::from_be_bytes([x[0], x[1]]),
+        })
+        .collect();
+    String::from_utf16(&units).map_err(|_| NdefError::Malformed("invalid UTF-16 text".into()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NdefMessage {
+    pub records: Vec<NdefRecord>,
+}
+
+impl NdefMessage {
+    pub fn new(records: Vec<NdefRecord>) -> Self {
+        Self { records }
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, NdefError> {
+        self.to_vec_chunked(usize::MAX)
+    }
+
+    /// Encodes message splitting payloads longer than `chunk_size` into chunked records
+    pub fn to_vec_chunked(&self, chunk_size: usize) -> Result<Vec<u8>, NdefError> {
+        // Payload length of a record is at most 4 bytes
+        let chunk_size = chunk_size.clamp(1, u32::MAX as usize);
+        let mut bytes = vec![];
+        let last = self.records.len().saturating_sub(1);
+
+        for (index, record) in self.records.iter().enumerate() {
+            if record.record_type.len() > 0xFF {
+                return Err(NdefError::Malformed(
+                    "record type is longer than 255 bytes".into(),
+                ));
+            }
+            if record.id.len() > 0xFF {
+                return Err(NdefError::Malformed(
+                    "record id is longer than 255 bytes".into(),
+                ));
+            }
+
+            let chunks: Vec<&[u8]> = match record.payload.is_empty() {
+                true => vec![&[]],
+                false => record.payload.chunks(chunk_size).collect(),
+            };
+            let last_chunk = chunks.len() - 1;
+
+            for (chunk_index, chunk) in chunks.iter().enumerate() {
+                let first = chunk_index == 0;
+                let mut header = if first {
+                    record.tnf.code()
+                } else {
+                    Tnf::Unchanged.code()
+                };
+                if index == 0 && first {
+                    header |= MB;
+                }
+                if index == last && chunk_index == last_chunk {
+                    header |= ME;
+                }
+                if chunk_index != last_chunk {
+                    header |= CF;
+                }
+                if chunk.len() < 256 {
+                    header |= SR;
+                }
+                if first && !record.id.is_empty() {
+                    header |= IL;
+                }
+
+                bytes.push(header);
+                bytes.push(if first {
+                    record.record_type.len() as u8
+                } else {
+                    0
+                });
+                if chunk.len() < 256 {
+                    bytes.push(chunk.len() as u8);
+                } else {
+                    bytes.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+                }
+                if first && !record.id.is_empty() {
+                    bytes.push(record.id.len() as u8);
+                }
+                if first {
+                    bytes.extend_from_slice(&record.record_type);
+                    bytes.extend_from_slice(&record.id);
+                }
+                bytes.extend_from_slice(chunk);
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NdefError> {
+        let malformed = |x: &str| NdefError::Malformed(x.to_owned());
+
+        let mut records = vec![];
+        let mut chunked: Option<NdefRecord> = None;
+        let mut position = 0;
+        let mut end = false;
+
+        while !end {
+            let header = *bytes
+                .get(position)
+                .ok_or_else(|| malformed("missing message end"))?;
+            let take = |position: &mut usize, len: usize| -> Result<&[u8], NdefError> {
+                let truncated = || NdefError::Malformed("record is truncated".into());
+                let end = position.checked_add(len).ok_or_else(truncated)?;
+                let data = bytes.get(*position..end).ok_or_else(truncated)?;
+                *position = end;
+                Ok(data)
+            };
+            position += 1;
+
+            if records.is_empty() && chunked.is_none() && header & MB == 0 {
+                return Err(malformed("missing message begin"));
+            }
+            end = header & ME != 0;
+
+            let type_len = take(&mut position, 1)?[0] as usize;
+            let payload_len = if header & SR != 0 {
+                take(&mut position, 1)?[0] as usize
+            } else {
+                let len = take(&mut position, 4)?;
+                u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize
+            };
+            let id_len = if header & IL != 0 {
+                take(&mut position, 1)?[0] as usize
+            } else {
+                0
+            };
+            let record_type = take(&mut position, type_len)?.to_vec();
+            let id = take(&mut position, id_len)?.to_vec();
+            let payload = take(&mut position, payload_len)?;
+            let tnf = Tnf::from_code(header);
+
+            match chunked.as_mut() {
+                Some(record) => {
+                    if tnf != Tnf::Unchanged || type_len != 0 || id_len != 0 {
+                        return Err(malformed("invalid middle or terminating chunk"));
+                    }
+                    record.payload.extend_from_slice(payload);
+                    if header & CF == 0 {
+                        records.extend(chunked.take());
+                    }
+                }
+                None => {
+                    if tnf == Tnf::Unchanged {
+                        return Err(malformed("unexpected chunk"));
+                    }
+                    let record = NdefRecord {
+                        tnf,
+                        record_type,
+                        id,
+                        payload: payload.to_vec(),
+                    };
+                    if header & CF != 0 {
+                        chunked = Some(record);
+                    } else {
+                        records.push(record);
+                    }
+                }
+            }
+        }
+
+        if chunked.is_some() {
+            return Err(malformed("message ends inside chunked record"));
+        }
+        Ok(Self { records })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uri_record_test() {
+        let record = NdefRecord::uri("https://www.example.com/a");
+        assert_eq!(record.payload[0], 0x02);
+        assert_eq!(&record.payload[1..], b"example.com/a");
+        assert_eq!(
+            record.content().unwrap(),
+            NdefContent::Uri("https://www.example.com/a".into())
+        );
+
+        let message = NdefMessage::new(vec![record]);
+        assert_eq!(
+            message.to_vec().unwrap(),
+            vec![
+                0xD1, 0x01, 0x0E, 0x55, 0x02, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c',
+                b'o', b'm', b'/', b'a'
+            ]
+        );
+        assert_eq!(NdefMessage::from_bytes(&message.to_vec().unwrap()).unwrap(), message);
+    }
+
+    #[test]
+    fn text_record_test() {
+        let record = NdefRecord::text("ru", "Привет");
+        assert_eq!(
+            record.content().unwrap(),
+            NdefContent::Text {
+                language: "ru".into(),
+                text: "Привет".into()
+            }
+        );
+
+        let utf16 = NdefRecord::new(
+            Tnf::WellKnown,
+            b"T",
+            vec![0x82, b'e', b'n', 0x00, b'H', 0x00, b'i'],
+        );
+        assert_eq!(
+            utf16.content().unwrap(),
+            NdefContent::Text {
+                language: "en".into(),
+                text: "Hi".into()
+            }
+        );
+    }
+
+    #[test]
+    fn smart_poster_test() {
+        let record = NdefRecord::smart_poster("tel:+123", &[("en", "Call us")]);
+        let message = NdefMessage::new(vec![record]);
+        let decoded = NdefMessage::from_bytes(&message.to_vec().unwrap()).unwrap();
+        assert_eq!(
+            decoded.records[0].content().unwrap(),
+            NdefContent::SmartPoster {
+                uri: "tel:+123".into(),
+                titles: vec![("en".into(), "Call us".into())]
+            }
+        );
+    }
+
+    #[test]
+    fn long_and_chunked_record_test() {
+        let vcard = "BEGIN:VCARD\nVERSION:3.0\nFN:".to_owned() + &"A".repeat(300) + "\nEND:VCARD";
+        let message = NdefMessage::new(vec![
+            NdefRecord::mime("text/vcard", vcard.as_bytes().to_vec()).with_id(b"1".to_vec()),
+            NdefRecord::uri("http://a.b"),
+        ]);
+
+        let bytes = message.to_vec().unwrap();
+        // Long record has 4 byte payload length
+        assert_eq!(bytes[0] & (SR | MB | IL), MB | IL);
+        assert_eq!(NdefMessage::from_bytes(&bytes).unwrap(), message);
+
+        let chunked = message.to_vec_chunked(100).unwrap();
+        assert_eq!(chunked[0] & CF, CF);
+        assert_eq!(NdefMessage::from_bytes(&chunked).unwrap(), message);
+
+        assert!(NdefMessage::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(NdefMessage::from_bytes(&[0x11, 0x01, 0x00, 0x55]).is_err());
+    }
+
+    #[test]
+    fn record_length_test() {
+        let long_type = NdefMessage::new(vec![NdefRecord::mime(&"a".repeat(256), vec![])]);
+        assert!(matches!(long_type.to_vec(), Err(NdefError::Malformed(_))));
+
+        let long_id = NdefMessage::new(vec![NdefRecord::uri("http://a.b").with_id(vec![0; 256])]);
+        assert!(matches!(long_id.to_vec(), Err(NdefError::Malformed(_))));
+
+        // Long record declaring 4 GB payload
+        assert!(matches!(
+            NdefMessage::from_bytes(&[0xC1, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x55, 0x00]),
+            Err(NdefError::Malformed(_))
+        ));
+    }
+}
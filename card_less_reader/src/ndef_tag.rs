@@ -0,0 +1,394 @@
+use crate::apdu;
+use crate::card;
+use crate::error;
+use crate::mifare;
+use crate::ndef;
+
+use apdu::{CommandApdu, ResponseApdu};
+use card::CardAccess;
+use error::{DeviceError, NdefError};
+use mifare::{MifareUltralight, PAGE_SIZE};
+use ndef::NdefMessage;
+
+/// Page of the capability container of Type 2 tag
+const T2T_CC_PAGE: u8 = 3;
+/// First page of Type 2 tag data area
+const T2T_DATA_PAGE: usize = 4;
+/// Pages addressable by READ and WRITE commands without sector select
+const T2T_PAGES: usize = 0x100;
+
+const TLV_NULL: u8 = 0x00;
+const TLV_NDEF: u8 = 0x03;
+const TLV_TERMINATOR: u8 = 0xFE;
+
+/// NFC Forum Type 2 tag, e.g. MIFARE Ultralight or NTAG
+///
+/// Lock and memory control TLVs are skipped, reserved areas they describe are not.
+pub struct Type2Tag<'a> {
+    card: &'a dyn MifareUltralight,
+}
+
+impl<'a> Type2Tag<'a> {
+    pub fn new(card: &'a dyn MifareUltralight) -> Self {
+        Self { card }
+    }
+
+    /// Returns size of the data area and whether the tag is writable
+    ///
+    /// Data areas beyond 8-bit page addresses are rejected, sector select is not supported.
+    fn read_cc(&self) -> Result<(usize, bool), NdefError> {
+        let pages = self.card.read_pages(T2T_CC_PAGE)?;
+        match pages[0..4] {
+            [0xE1, version, size, access] if version >> 4 == 1 => {
+                let size = size as usize * 8;
+                if size > (T2T_PAGES - T2T_DATA_PAGE) * PAGE_SIZE {
+                    return Err(NdefError::NotFormatted(format!(
+                        "data area of {} bytes exceeds 8-bit page addresses",
+                        size
+                    )));
+                }
+                Ok((size, access == 0x00))
+            }
+            _ => Err(NdefError::NotFormatted(format!(
+                "capability container {:02X?}",
+                &pages[0..4]
+            ))),
+        }
+    }
+
+    /// Reads `size` bytes checked by `read_cc`, so every page fits in `u8`
+    fn read_data_area(&self, size: usize) -> Result<Vec<u8>, NdefError> {
+        let mut data = Vec::with_capacity(size + 16);
+        let mut page = T2T_DATA_PAGE;
+        while data.len() < size {
+            data.extend_from_slice(&self.card.read_pages(page as u8)?);
+            page += 4;
+        }
+        data.truncate(size);
+        Ok(data)
+    }
+
+    pub fn read_ndef(&self) -> Result<Option<NdefMessage>, NdefError> {
+        let (size, _) = self.read_cc()?;
+        let data = self.read_data_area(size)?;
+
+        match find_ndef_tlv(&data)? {
+            (_, Some(value)) if !value.is_empty() => Ok(Some(NdefMessage::from_bytes(value)?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn write_ndef(&self, message: &NdefMessage) -> Result<(), NdefError> {
+        let (size, writable) = self.read_cc()?;
+        if !writable {
+            return Err(NdefError::ReadOnly);
+        }
+        let data = self.read_data_area(size)?;
+        let (offset, _) = find_ndef_tlv(&data)?;
+
+        let message = message.to_vec()?;
+        let mut tlv = vec![TLV_NDEF];
+        if message.len() < 0xFF {
+            tlv.push(message.len() as u8);
+        } else {
+            tlv.push(0xFF);
+            tlv.extend_from_slice(&(message.len() as u16).to_be_bytes());
+        }
+        tlv.extend_from_slice(&message);
+
+        let capacity = size - offset;
+        if tlv.len() > capacity || message.len() > 0xFFFE {
+            return Err(NdefError::TooLarge {
+                size: message.len(),
+                capacity,
+            });
+        }
+        if tlv.len() < capacity {
+            tlv.push(TLV_TERMINATOR);
+        }
+
+        // Pages are written whole, keeping bytes before the NDEF TLV
+        let start = offset - offset % PAGE_SIZE;
+        let mut area = data[start..offset].to_vec();
+        area.extend_from_slice(&tlv);
+        while area.len() % PAGE_SIZE != 0 {
+            area.push(TLV_NULL);
+        }
+
+        // The area ends within `size` checked by `read_cc`, so every page fits in `u8`
+        let first_page = T2T_DATA_PAGE + start / PAGE_SIZE;
+        for (index, page) in area.chunks(PAGE_SIZE).enumerate() {
+            let mut bytes = [0; PAGE_SIZE];
+            bytes.copy_from_slice(page);
+            self.card.write_page((first_page + index) as u8, &bytes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns offset of the NDEF TLV, or where it should be written, and its value
+fn find_ndef_tlv(data: &[u8]) -> Result<(usize, Option<&[u8]>), NdefError> {
+    let truncated = || NdefError::Malformed("TLV is truncated".into());
+
+    let mut position = 0;
+    while position < data.len() {
+        let tag = data[position];
+        match tag {
+            TLV_NULL => {
+                position += 1;
+                continue;
+            }
+            TLV_TERMINATOR => break,
+            _ => {}
+        }
+
+        let (len, header) = match data.get(position + 1) {
+            Some(0xFF) => match data.get(position + 2..position + 4) {
+                Some(len) => (u16::from_be_bytes([len[0], len[1]]) as usize, 4),
+                None => return Err(truncated()),
+            },
+            Some(len) => (*len as usize, 2),
+            None => return Err(truncated()),
+        };
+        let value = data
+            .get(position + header..position + header + len)
+            .ok_or_else(truncated)?;
+
+        if tag == TLV_NDEF {
+            return Ok((position, Some(value)));
+        }
+        position += header + len;
+    }
+
+    Ok((position.min(data.len()), None))
+}
+
+const NDEF_APPLICATION: [u8; 7] = [0xD2, 0x76, 0x00, 0x00, 0x85, 0x01, 0x01];
+const CC_FILE: [u8; 2] = [0xE1, 0x03];
+
+/// Content of Type 4 tag capability container
+struct Type4Cc {
+    max_read: usize,
+    max_write: usize,
+    file_id: [u8; 2],
+    max_size: usize,
+    writable: bool,
+}
+
+/// NFC Forum Type 4 tag, e.g. DESFire or a smart card with the NDEF application
+pub struct Type4Tag<'a> {
+    card: &'a dyn CardAccess,
+}
+
+impl<'a> Type4Tag<'a> {
+    pub fn new(card: &'a dyn CardAccess) -> Self {
+        Self { card }
+    }
+
+    fn transceive(&self, command: CommandApdu) -> Result<ResponseApdu, NdefError> {
+        let response = self.card.transceive(&command)?;
+        if !response.is_success() {
+            return Err(DeviceError::CardStatus(response.sw).into());
+        }
+        Ok(response)
+    }
+
+    fn select_file(&self, file_id: [u8; 2]) -> Result<(), NdefError> {
+        self.transceive(CommandApdu::new(0x00, 0xA4, 0x00, 0x0C).with_data(file_id.to_vec()))?;
+        Ok(())
+    }
+
+    fn read_binary(&self, offset: usize, len: usize) -> Result<Vec<u8>, NdefError> {
+        let command = CommandApdu::new(0x00, 0xB0, (offset >> 8) as u8, offset as u8).with_le(len);
+        let response = self.transceive(command)?;
+        if response.data.len() != len {
+            return Err(NdefError::Malformed(format!(
+                "expected {} bytes at offset {}, read {}",
+                len,
+                offset,
+                response.data.len()
+            )));
+        }
+        Ok(response.data)
+    }
+
+    fn update_binary(&self, offset: usize, data: &[u8]) -> Result<(), NdefError> {
+        self.transceive(
+            CommandApdu::new(0x00, 0xD6, (offset >> 8) as u8, offset as u8)
+                .with_data(data.to_vec()),
+        )?;
+        Ok(())
+    }
+
+    fn select_ndef(&self) -> Result<Type4Cc, NdefError> {
+        match self
+            .card
+            .transceive(&CommandApdu::select(&NDEF_APPLICATION))?
+            .sw
+        {
+            0x9000 => {}
+            sw => {
+                return Err(NdefError::NotFormatted(format!(
+                    "NDEF application selection returned {:04X}",
+                    sw
+                )))
+            }
+        }
+
+        self.select_file(CC_FILE)?;
+        let cc = self.read_binary(0, 15)?;
+        // CCLEN, mapping version, MLe, MLc, then NDEF File Control TLV
+        if cc[7] != 0x04 || cc[8] != 0x06 {
+            return Err(NdefError::NotFormatted(format!(
+                "capability container {:02X?}",
+                cc
+            )));
+        }
+
+        let max_size = u16::from_be_bytes([cc[11], cc[12]]) as usize;
+        let cc = Type4Cc {
+            max_read: (u16::from_be_bytes([cc[3], cc[4]]) as usize).max(1),
+            max_write: (u16::from_be_bytes([cc[5], cc[6]]) as usize).max(1),
+            file_id: [cc[9], cc[10]],
+            // Offsets of READ BINARY are limited to 15 bits
+            max_size: max_size.min(0x7FFF),
+            writable: cc[14] == 0x00,
+        };
+        self.select_file(cc.file_id)?;
+        Ok(cc)
+    }
+
+    pub fn read_ndef(&self) -> Result<Option<NdefMessage>, NdefError> {
+        let cc = self.select_ndef()?;
+
+        let nlen = self.read_binary(0, 2)?;
+        let nlen = u16::from_be_bytes([nlen[0], nlen[1]]) as usize;
+        if nlen == 0 {
+            return Ok(None);
+        }
+        if nlen + 2 > cc.max_size {
+            return Err(NdefError::Malformed(format!(
+                "NDEF length {} exceeds file size {}",
+                nlen, cc.max_size
+            )));
+        }
+
+        let mut message = Vec::with_capacity(nlen);
+        while message.len() < nlen {
+            let len = cc.max_read.min(nlen - message.len());
+            message.extend(self.read_binary(2 + message.len(), len)?);
+        }
+        Ok(Some(NdefMessage::from_bytes(&message)?))
+    }
+
+    pub fn write_ndef(&self, message: &NdefMessage) -> Result<(), NdefError> {
+        let cc = self.select_ndef()?;
+        if !cc.writable {
+            return Err(NdefError::ReadOnly);
+        }
+
+        let message = message.to_vec()?;
+        if message.len() + 2 > cc.max_size {
+            return Err(NdefError::TooLarge {
+                size: message.len(),
+                capacity: cc.max_size - 2,
+            });
+        }
+
+        // The length is set last, so an interrupted write leaves an empty tag
+        self.update_binary(0, &[0x00, 0x00])?;
+        for (index, chunk) in message.chunks(cc.max_write).enumerate() {
+            self.update_binary(2 + index * cc.max_write, chunk)?;
+        }
+        self.update_binary(0, &(message.len() as u16).to_be_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::CancellationToken;
+    use crate::mifare::PcscMemoryCard;
+    use crate::ndef::{NdefContent, NdefRecord};
+    use crate::simulated_card::SimulatedCard;
+
+    #[test]
+    fn type2_tag_test() {
+        let card = SimulatedCard::ntag213(vec![0x04, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        card.activate(&CancellationToken::new()).unwrap();
+        let ultralight = PcscMemoryCard::new(&card);
+        let tag = Type2Tag::new(&ultralight);
+
+        assert_eq!(tag.read_ndef().unwrap(), None);
+
+        let message = NdefMessage::new(vec![NdefRecord::uri("https://example.com")]);
+        tag.write_ndef(&message).unwrap();
+        assert_eq!(tag.read_ndef().unwrap(), Some(message));
+
+        let long = NdefMessage::new(vec![NdefRecord::text("en", &"x".repeat(120))]);
+        tag.write_ndef(&long).unwrap();
+        let read = tag.read_ndef().unwrap().unwrap();
+        assert_eq!(read, long);
+
+        let too_long = NdefMessage::new(vec![NdefRecord::text("en", &"x".repeat(1000))]);
+        assert!(matches!(
+            tag.write_ndef(&too_long),
+            Err(NdefError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn type2_tag_size_test() {
+        let card =
+            SimulatedCard::mifare_ultralight(vec![0x04, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06], 256);
+        card.activate(&CancellationToken::new()).unwrap();
+        let ultralight = PcscMemoryCard::new(&card);
+        let tag = Type2Tag::new(&ultralight);
+
+        // 252 data pages after page 3 still have 8-bit addresses
+        ultralight.write_page(3, &[0xE1, 0x10, 0x7E, 0x00]).unwrap();
+        ultralight.write_page(4, &[0x03, 0x00, 0xFE, 0x00]).unwrap();
+        assert_eq!(tag.read_ndef().unwrap(), None);
+
+        let message = NdefMessage::new(vec![NdefRecord::text("en", &"x".repeat(900))]);
+        tag.write_ndef(&message).unwrap();
+        assert_eq!(tag.read_ndef().unwrap(), Some(message));
+
+        ultralight.write_page(3, &[0xE1, 0x10, 0x7F, 0x00]).unwrap();
+        assert!(matches!(tag.read_ndef(), Err(NdefError::NotFormatted(_))));
+    }
+
+    #[test]
+    fn type4_tag_test() {
+        let card = SimulatedCard::type4_tag(vec![0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66], 1024);
+        card.activate(&CancellationToken::new()).unwrap();
+        let tag = Type4Tag::new(&card);
+
+        assert_eq!(tag.read_ndef().unwrap(), None);
+
+        let vcard = b"BEGIN:VCARD\nVERSION:3.0\nFN:Jane Doe\nEND:VCARD".to_vec();
+        let message = NdefMessage::new(vec![
+            NdefRecord::mime("text/vcard", vcard.clone()),
+            NdefRecord::smart_poster("https://example.com", &[("en", &"y".repeat(200))]),
+        ]);
+        tag.write_ndef(&message).unwrap();
+
+        let read = tag.read_ndef().unwrap().unwrap();
+        assert_eq!(read, message);
+        assert_eq!(
+            read.records[0].content().unwrap(),
+            NdefContent::Mime {
+                mime_type: "text/vcard".into(),
+                data: vcard
+            }
+        );
+
+        let memory_card = SimulatedCard::mifare_classic_1k(vec![1, 2, 3, 4]);
+        memory_card.activate(&CancellationToken::new()).unwrap();
+        assert!(matches!(
+            Type4Tag::new(&memory_card).read_ndef(),
+            Err(NdefError::NotFormatted(_))
+        ));
+    }
+}
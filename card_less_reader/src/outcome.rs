@@ -0,0 +1,189 @@
+use crate::tag_value;
+use crate::tlv_parser;
+
+use tag_value::AnnexE;
+use tlv_parser::Tlv;
+
+/// Outcome of a contactless transaction, see EMV Book A 9.2
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutcomeKind {
+    Approved,
+    Declined,
+    /// The card produced an ARQC, authorization must be requested from the issuer
+    OnlineRequest,
+    /// The card should be presented again
+    TryAgain,
+    /// The cardholder should follow instructions on the phone and present it again
+    SeePhone,
+    /// The transaction may be performed over contact chip or magnetic stripe
+    TryAnotherInterface,
+    EndApplication,
+}
+
+impl OutcomeKind {
+    /// Maps Cryptogram Information Data (9F27) to an outcome
+    pub fn from_cid(cid: u8) -> Self {
+        match cid & 0xC0 {
+            0x40 => OutcomeKind::Approved,
+            0x80 => OutcomeKind::OnlineRequest,
+            0x00 => OutcomeKind::Declined,
+            _ => OutcomeKind::EndApplication,
+        }
+    }
+
+    /// Maps a status code of the reader to an outcome, codes without a name end the transaction
+    pub fn from_annex_e(code: AnnexE) -> Self {
+        match code {
+            AnnexE::CollisionMoreThanOnePICCDetected => OutcomeKind::TryAgain,
+            AnnexE::EmvTransactionTerminated => OutcomeKind::EndApplication,
+            AnnexE::EmvTransactionTerminatedSeePhone => OutcomeKind::SeePhone,
+            AnnexE::EmvTransactionTerminatedUseContactChannel => OutcomeKind::TryAnotherInterface,
+            AnnexE::EmvTransactionTerminatedTryAgain => OutcomeKind::TryAgain,
+            AnnexE::Unknown(_) => OutcomeKind::EndApplication,
+        }
+    }
+}
+
+/// Cardholder verification method performed or requested by the card
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Cvm {
+    NoCvm,
+    Signature,
+    OnlinePin,
+    OfflinePin,
+    /// Offline PIN verified and a signature is collected on the receipt
+    OfflinePinAndSignature,
+    NotApplicable,
+}
+
+impl Cvm {
+    /// Maps CVM Results (9F34) to the method
+    ///
+    /// Byte 1 holds the CVM Code of EMV Book 3 Annex C3, `3F` if no CVM was performed
+    /// (Book 3 Annex A). Fail CVM processing (`00`) and RFU codes are `NotApplicable`.
+    pub fn from_cvm_results(cvm_results: &[u8]) -> Self {
+        match cvm_results.first().map(|x| x & 0x3F) {
+            // plaintext, enciphered PIN verified by ICC
+            Some(0x01) | Some(0x04) => Cvm::OfflinePin,
+            // plaintext, enciphered PIN verified by ICC and signature (paper)
+            Some(0x03) | Some(0x05) => Cvm::OfflinePinAndSignature,
+            // enciphered PIN verified online
+            Some(0x02) => Cvm::OnlinePin,
+            // signature (paper)
+            Some(0x1E) => Cvm::Signature,
+            // no CVM required, no CVM performed
+            Some(0x1F) | Some(0x3F) => Cvm::NoCvm,
+            _ => Cvm::NotApplicable,
+        }
+    }
+
+    /// The cardholder must sign the receipt
+    pub fn signature_required(self) -> bool {
+        matches!(self, Cvm::Signature | Cvm::OfflinePinAndSignature)
+    }
+}
+
+/// Prompt the POS should show to the cardholder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UiRequest {
+    pub message: String,
+    /// Code reported by the reader, if the prompt comes from an `AnnexE` status
+    pub annex_e: Option<AnnexE>,
+}
+
+impl From<AnnexE> for UiRequest {
+    fn from(code: AnnexE) -> Self {
+        Self {
+            message: code.user_prompt().to_owned(),
+            annex_e: Some(code),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PollOutcome {
+    pub kind: OutcomeKind,
+    pub cvm: Cvm,
+    /// A receipt should be printed
+    pub receipt: bool,
+    pub ui_request: Option<UiRequest>,
+    /// Response of the reader as is
+    pub tlv: Tlv,
+}
+
+impl PollOutcome {
+    /// Outcome of the transaction terminated by the reader with `code`
+    pub fn from_annex_e(code: AnnexE, tlv: Tlv) -> Self {
+        Self {
+            kind: OutcomeKind::from_annex_e(code),
+            cvm: Cvm::NotApplicable,
+            receipt: false,
+            ui_request: Some(code.into()),
+            tlv,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcome_kind_test() {
+        assert_eq!(OutcomeKind::from_cid(0x40), OutcomeKind::Approved);
+        assert_eq!(OutcomeKind::from_cid(0x80), OutcomeKind::OnlineRequest);
+        assert_eq!(OutcomeKind::from_cid(0x00), OutcomeKind::Declined);
+
+        assert_eq!(
+            OutcomeKind::from_annex_e(AnnexE::CollisionMoreThanOnePICCDetected),
+            OutcomeKind::TryAgain
+        );
+        assert_eq!(
+            OutcomeKind::from_annex_e(AnnexE::EmvTransactionTerminated),
+            OutcomeKind::EndApplication
+        );
+        assert_eq!(
+            OutcomeKind::from_annex_e(AnnexE::EmvTransactionTerminatedTryAgain),
+            OutcomeKind::TryAgain
+        );
+        assert_eq!(
+            OutcomeKind::from_annex_e(AnnexE::EmvTransactionTerminatedSeePhone),
+            OutcomeKind::SeePhone
+        );
+        assert_eq!(
+            OutcomeKind::from_annex_e(AnnexE::EmvTransactionTerminatedUseContactChannel),
+            OutcomeKind::TryAnotherInterface
+        );
+        assert_eq!(
+            OutcomeKind::from_annex_e(AnnexE::Unknown(0x42)),
+            OutcomeKind::EndApplication
+        );
+
+        assert_eq!(Cvm::from_cvm_results(&[0x42, 0x03, 0x00]), Cvm::OnlinePin);
+        assert_eq!(Cvm::from_cvm_results(&[0x1F, 0x00, 0x02]), Cvm::NoCvm);
+        assert_eq!(Cvm::from_cvm_results(&[0x3F, 0x00, 0x00]), Cvm::NoCvm);
+        assert_eq!(
+            Cvm::from_cvm_results(&[0x2F, 0x00, 0x02]),
+            Cvm::NotApplicable
+        );
+        assert_eq!(
+            Cvm::from_cvm_results(&[0x00, 0x00, 0x01]),
+            Cvm::NotApplicable
+        );
+        assert_eq!(Cvm::from_cvm_results(&[]), Cvm::NotApplicable);
+        assert_eq!(Cvm::from_cvm_results(&[0x44, 0x00, 0x02]), Cvm::OfflinePin);
+        assert_eq!(
+            Cvm::from_cvm_results(&[0x03, 0x00, 0x02]),
+            Cvm::OfflinePinAndSignature
+        );
+        assert_eq!(
+            Cvm::from_cvm_results(&[0x45, 0x00, 0x02]),
+            Cvm::OfflinePinAndSignature
+        );
+
+        assert!(Cvm::Signature.signature_required());
+        assert!(Cvm::OfflinePinAndSignature.signature_required());
+        assert!(!Cvm::OfflinePin.signature_required());
+        assert!(!Cvm::NoCvm.signature_required());
+    }
+}
@@ -0,0 +1,397 @@
+use crate::apdu;
+use crate::card;
+use crate::device;
+use crate::error;
+use crate::mifare;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use apdu::{CommandApdu, ResponseApdu};
+use card::{CardAccess, CardInfo, CardTechnology, CardType, PollCardResult};
+use device::CancellationToken;
+use error::DeviceError;
+use mifare::{sector_trailer, BLOCK_SIZE, PAGE_SIZE};
+
+const NDEF_APPLICATION: [u8; 7] = [0xD2, 0x76, 0x00, 0x00, 0x85, 0x01, 0x01];
+const CC_FILE: u16 = 0xE103;
+const NDEF_FILE: u16 = 0xE104;
+
+const SW_SUCCESS: u16 = 0x9000;
+const SW_WRONG_LENGTH: u16 = 0x6700;
+const SW_SECURITY_NOT_SATISFIED: u16 = 0x6982;
+const SW_AUTHENTICATION_FAILED: u16 = 0x6300;
+const SW_WRONG_PARAMETERS: u16 = 0x6B00;
+const SW_FILE_NOT_FOUND: u16 = 0x6A82;
+const SW_NOT_ENOUGH_MEMORY: u16 = 0x6A84;
+const SW_INS_NOT_SUPPORTED: u16 = 0x6D00;
+const SW_CLA_NOT_SUPPORTED: u16 = 0x6E00;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Memory {
+    /// 16 byte blocks protected by sector keys
+    Classic,
+    /// 4 byte pages
+    Ultralight,
+    /// ISO/IEC 7816-4 files of the NDEF application
+    Type4,
+}
+
+struct State {
+    active: bool,
+    memory: Vec<u8>,
+    keys: HashMap<u8, [u8; 6]>,
+    /// Trailer block of the authenticated sector
+    authenticated: Option<u8>,
+    files: HashMap<u16, Vec<u8>>,
+    application_selected: bool,
+    selected_file: Option<u16>,
+}
+
+impl State {
+    /// Forgets everything the card loses with the field
+    fn reset(&mut self) {
+        self.active = false;
+        self.authenticated = None;
+        self.application_selected = false;
+        self.selected_file = None;
+    }
+}
+
+/// Card emulated in memory, for testing without a reader
+///
+/// Answers PC/SC pseudo-APDUs like a reader with the card in the field.
+/// Access bits of MIFARE Classic sector trailers are not enforced.
+pub struct SimulatedCard {
+    info: CardInfo,
+    kind: Memory,
+    state: Mutex<State>,
+}
+
+impl SimulatedCard {
+    pub fn mifare_classic_1k(uid: Vec<u8>) -> Self {
+        Self::mifare_classic(uid, 0x08, 64)
+    }
+
+    pub fn mifare_classic_4k(uid: Vec<u8>) -> Self {
+        Self::mifare_classic(uid, 0x18, 256)
+    }
+
+    /// Ultralight or NTAG with `pages` pages and 7 byte `uid`
+    pub fn mifare_ultralight(uid: Vec<u8>, pages: usize) -> Self {
+        let mut memory = vec![0; pages.max(4) * PAGE_SIZE];
+        if uid.len() == 7 {
+            memory[0..3].copy_from_slice(&uid[0..3]);
+            memory[3] = 0x88 ^ uid[0] ^ uid[1] ^ uid[2];
+            memory[4..8].copy_from_slice(&uid[3..7]);
+            memory[8] = uid[3] ^ uid[4] ^ uid[5] ^ uid[6];
+        }
+
+        Self::new(
+            Self::type_a_info(uid, [0x44, 0x00], 0x00),
+            Memory::Ultralight,
+            memory,
+        )
+    }
+
+    /// NTAG213 formatted as an empty NDEF tag
+    pub fn ntag213(uid: Vec<u8>) -> Self {
+        let card = Self::mifare_ultralight(uid, 45);
+        {
+            let mut state = card.state.lock().unwrap();
+            state.memory[12..16].copy_from_slice(&[0xE1, 0x10, 0x12, 0x00]);
+            state.memory[16..19].copy_from_slice(&[0x03, 0x00, 0xFE]);
+        }
+        card
+    }
+
+    /// ISO/IEC 14443-4 card with the NDEF application and an empty NDEF file of `size` bytes
+    pub fn type4_tag(uid: Vec<u8>, size: u16) -> Self {
+        let mut info = Self::type_a_info(uid, [0x44, 0x03], 0x20);
+        info.ats = Some(vec![0x06, 0x77, 0x77, 0x71, 0x02, 0x80]);
+
+        let card = Self::new(info, Memory::Type4, vec![]);
+        {
+            let mut state = card.state.lock().unwrap();
+            let mut cc = vec![0x00, 0x0F, 0x20, 0x00, 0x3B, 0x00, 0x34, 0x04, 0x06];
+            cc.extend_from_slice(&NDEF_FILE.to_be_bytes());
+            cc.extend_from_slice(&size.to_be_bytes());
+            cc.extend_from_slice(&[0x00, 0x00]);
+            state.files.insert(CC_FILE, cc);
+            state.files.insert(NDEF_FILE, vec![0; size as usize]);
+        }
+        card
+    }
+
+    fn mifare_classic(uid: Vec<u8>, sak: u8, blocks: usize) -> Self {
+        let mut memory = vec![0; blocks * BLOCK_SIZE];
+        memory[0..uid.len()].copy_from_slice(&uid);
+        for block in 0..blocks {
+            if sector_trailer(block as u8) as usize == block {
+                let trailer = &mut memory[block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE];
+                trailer[0..6].copy_from_slice(&[0xFF; 6]);
+                trailer[6..10].copy_from_slice(&[0xFF, 0x07, 0x80, 0x69]);
+                trailer[10..16].copy_from_slice(&[0xFF; 6]);
+            }
+        }
+
+        Self::new(
+            Self::type_a_info(uid, [0x00, 0x04], sak),
+            Memory::Classic,
+            memory,
+        )
+    }
+
+    fn type_a_info(uid: Vec<u8>, atqa: [u8; 2], sak: u8) -> CardInfo {
+        CardInfo {
+            technology: CardTechnology::IsoA,
+            uid,
+            atqa: Some(atqa),
+            sak: Some(sak),
+            atqb: None,
+            ats: None,
+            card_type: CardType::from_sak(sak),
+        }
+    }
+
+    fn new(info: CardInfo, kind: Memory, memory: Vec<u8>) -> Self {
+        Self {
+            info,
+            kind,
+            state: Mutex::new(State {
+                active: false,
+                memory,
+                keys: HashMap::new(),
+                authenticated: None,
+                files: HashMap::new(),
+                application_selected: false,
+                selected_file: None,
+            }),
+        }
+    }
+
+    /// Returns copy of the card memory
+    pub fn memory(&self) -> Vec<u8> {
+        self.state.lock().unwrap().memory.clone()
+    }
+
+    fn process(&self, state: &mut State, command: &CommandApdu) -> ResponseApdu {
+        let result = match (command.cla, self.kind) {
+            (0xFF, Memory::Classic) | (0xFF, Memory::Ultralight) => match command.ins {
+                0x82 => Self::load_key(state, command),
+                0x86 => self.authenticate(state, command),
+                0xB0 => self.read_binary(state, command),
+                0xD6 => self.update_binary(state, command),
+                0xD7 => self.value_operation(state, command),
+                _ => Err(SW_INS_NOT_SUPPORTED),
+            },
+            (0x00, Memory::Type4) => match command.ins {
+                0xA4 => Self::select(state, command),
+                0xB0 => Self::read_file(state, command),
+                0xD6 => Self::update_file(state, command),
+                _ => Err(SW_INS_NOT_SUPPORTED),
+            },
+            _ => Err(SW_CLA_NOT_SUPPORTED),
+        };
+
+        match result {
+            Ok(data) => ResponseApdu::new(data, SW_SUCCESS),
+            Err(sw) => ResponseApdu::new(vec![], sw),
+        }
+    }
+
+    fn select(state: &mut State, command: &CommandApdu) -> Result<Vec<u8>, u16> {
+        match (command.p1, command.data.as_slice()) {
+            (0x04, name) if name == NDEF_APPLICATION => {
+                state.application_selected = true;
+                state.selected_file = None;
+                Ok(vec![])
+            }
+            (0x00, [high, low]) if state.application_selected => {
+                let file_id = u16::from_be_bytes([*high, *low]);
+                if !state.files.contains_key(&file_id) {
+                    return Err(SW_FILE_NOT_FOUND);
+                }
+                state.selected_file = Some(file_id);
+                Ok(vec![])
+            }
+            _ => Err(SW_FILE_NOT_FOUND),
+        }
+    }
+
+    /// Returns selected file and the offset of READ or UPDATE BINARY
+    fn selected_file<'s>(
+        state: &'s mut State,
+        command: &CommandApdu,
+    ) -> Result<(u16, &'s mut Vec<u8>, usize), u16> {
+        let file_id = state.selected_file.ok_or(SW_FILE_NOT_FOUND)?;
+        let file = state.files.get_mut(&file_id).ok_or(SW_FILE_NOT_FOUND)?;
+        let offset = u16::from_be_bytes([command.p1 & 0x7F, command.p2]) as usize;
+        if offset > file.len() {
+            return Err(SW_WRONG_PARAMETERS);
+        }
+        Ok((file_id, file, offset))
+    }
+
+    fn read_file(state: &mut State, command: &CommandApdu) -> Result<Vec<u8>, u16> {
+        let (_, file, offset) = Self::selected_file(state, command)?;
+        let end = file.len().min(offset + command.le.unwrap_or(256));
+        Ok(file[offset..end].to_vec())
+    }
+
+    fn update_file(state: &mut State, command: &CommandApdu) -> Result<Vec<u8>, u16> {
+        let (file_id, file, offset) = Self::selected_file(state, command)?;
+        if file_id == CC_FILE {
+            return Err(SW_SECURITY_NOT_SATISFIED);
+        }
+        if offset + command.data.len() > file.len() {
+            return Err(SW_NOT_ENOUGH_MEMORY);
+        }
+        file[offset..offset + command.data.len()].copy_from_slice(&command.data);
+        Ok(vec![])
+    }
+
+    fn load_key(state: &mut State, command: &CommandApdu) -> Result<Vec<u8>, u16> {
+        if command.data.len() != 6 {
+            return Err(SW_WRONG_LENGTH);
+        }
+        let mut key = [0; 6];
+        key.copy_from_slice(&command.data);
+        state.keys.insert(command.p2, key);
+        Ok(vec![])
+    }
+
+    fn authenticate(&self, state: &mut State, command: &CommandApdu) -> Result<Vec<u8>, u16> {
+        let (block, key_type, key_number) = match command.data.as_slice() {
+            [0x01, 0x00, block, key_type, key_number] => (*block, *key_type, *key_number),
+            _ => return Err(SW_WRONG_LENGTH),
+        };
+        if self.kind != Memory::Classic || (block as usize + 1) * BLOCK_SIZE > state.memory.len() {
+            return Err(SW_WRONG_PARAMETERS);
+        }
+
+        let trailer = sector_trailer(block);
+        let offset = trailer as usize * BLOCK_SIZE;
+        let expected = match key_type {
+            0x60 => &state.memory[offset..offset + 6],
+            0x61 => &state.memory[offset + 10..offset + 16],
+            _ => return Err(SW_WRONG_PARAMETERS),
+        };
+
+        state.authenticated = None;
+        match state.keys.get(&key_number) {
+            Some(key) if key[..] == *expected => {
+                state.authenticated = Some(trailer);
+                Ok(vec![])
+            }
+            _ => Err(SW_AUTHENTICATION_FAILED),
+        }
+    }
+
+    /// Returns offset of the classic block after checking authentication
+    fn classic_block(state: &State, block: u8) -> Result<usize, u16> {
+        let offset = block as usize * BLOCK_SIZE;
+        if offset + BLOCK_SIZE > state.memory.len() {
+            return Err(SW_WRONG_PARAMETERS);
+        }
+        if state.authenticated != Some(sector_trailer(block)) {
+            return Err(SW_SECURITY_NOT_SATISFIED);
+        }
+        Ok(offset)
+    }
+
+    fn read_binary(&self, state: &mut State, command: &CommandApdu) -> Result<Vec<u8>, u16> {
+        let address = command.p2;
+        match self.kind {
+            Memory::Classic => {
+                let offset = Self::classic_block(state, address)?;
+                Ok(state.memory[offset..offset + BLOCK_SIZE].to_vec())
+            }
+            _ => {
+                let len = state.memory.len();
+                let offset = address as usize * PAGE_SIZE;
+                if offset >= len {
+                    return Err(SW_WRONG_PARAMETERS);
+                }
+                Ok((0..BLOCK_SIZE)
+                    .map(|x| state.memory[(offset + x) % len])
+                    .collect())
+            }
+        }
+    }
+
+    fn update_binary(&self, state: &mut State, command: &CommandApdu) -> Result<Vec<u8>, u16> {
+        let address = command.p2;
+        let (offset, size) = match self.kind {
+            Memory::Classic => (Self::classic_block(state, address)?, BLOCK_SIZE),
+            _ => (address as usize * PAGE_SIZE, PAGE_SIZE),
+        };
+        if command.data.len() != size {
+            return Err(SW_WRONG_LENGTH);
+        }
+        if offset + size > state.memory.len() {
+            return Err(SW_WRONG_PARAMETERS);
+        }
+        state.memory[offset..offset + size].copy_from_slice(&command.data);
+        Ok(vec![])
+    }
+
+    fn value_operation(&self, state: &mut State, command: &CommandApdu) -> Result<Vec<u8>, u16> {
+        if self.kind != Memory::Classic {
+            return Err(SW_INS_NOT_SUPPORTED);
+        }
+        let source = Self::classic_block(state, command.p2)?;
+        let read_value = |state: &State, offset: usize| {
+            let mut block = [0; BLOCK_SIZE];
+            block.copy_from_slice(&state.memory[offset..offset + BLOCK_SIZE]);
+            mifare::decode_value_block(&block).ok_or(SW_WRONG_PARAMETERS)
+        };
+
+        match command.data.as_slice() {
+            [operation @ 0x01..=0x02, delta @ ..] if delta.len() == 4 => {
+                let (value, address) = read_value(state, source)?;
+                let delta = i32::from_be_bytes([delta[0], delta[1], delta[2], delta[3]]);
+                let value = match operation {
+                    0x01 => value.wrapping_add(delta),
+                    _ => value.wrapping_sub(delta),
+                };
+                state.memory[source..source + BLOCK_SIZE]
+                    .copy_from_slice(&mifare::encode_value_block(value, address));
+                Ok(vec![])
+            }
+            [0x03, target] => {
+                read_value(state, source)?;
+                let target = Self::classic_block(state, *target)?;
+                let block = state.memory[source..source + BLOCK_SIZE].to_vec();
+                state.memory[target..target + BLOCK_SIZE].copy_from_slice(&block);
+                Ok(vec![])
+            }
+            _ => Err(SW_WRONG_PARAMETERS),
+        }
+    }
+}
+
+impl CardAccess for SimulatedCard {
+    fn activate(&self, _cancel: &CancellationToken) -> Result<PollCardResult, DeviceError> {
+        let mut state = self.state.lock().unwrap();
+        state.reset();
+        state.active = true;
+        Ok(PollCardResult::Card(self.info.clone()))
+    }
+
+    fn transceive(&self, command: &CommandApdu) -> Result<ResponseApdu, DeviceError> {
+        // Checks that the command can be sent at all
+        command.to_vec()?;
+
+        let mut state = self.state.lock().unwrap();
+        if !state.active {
+            return Err(DeviceError::Other("card is not activated".into()));
+        }
+        Ok(self.process(&mut state, command))
+    }
+
+    fn deactivate(&self) -> Result<(), DeviceError> {
+        self.state.lock().unwrap().reset();
+        Ok(())
+    }
+}
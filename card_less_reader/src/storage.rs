@@ -0,0 +1,99 @@
+use crate::error;
+
+use std::io::{Cursor, Read};
+
+use error::StorageError;
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+pub const MAX_PATH_LEN: usize = 255;
+
+/// File system of a device or of the host
+///
+/// Paths are absolute and use `/` as a separator, e.g. `/images/logo.bmp`.
+/// The root directory is `/`.
+pub trait Storage {
+    fn dir_exist(&self, path: &str) -> StorageResult<bool>;
+
+    /// Returns names of the sub directories of `path`
+    fn get_dir_list(&self, path: &str) -> StorageResult<Vec<String>>;
+
+    /// Creates directory, the parent directory must exist
+    fn create_dir(&self, path: &str) -> StorageResult<()>;
+
+    /// Deletes empty directory
+    fn delete_dir(&self, path: &str) -> StorageResult<()>;
+
+    fn file_exist(&self, file_path: &str) -> StorageResult<bool>;
+
+    /// Returns names of the files of `path`
+    fn get_file_list(&self, path: &str) -> StorageResult<Vec<String>>;
+
+    fn delete_file(&self, file_path: &str) -> StorageResult<()>;
+
+    fn read_file(&self, file_path: &str) -> StorageResult<Vec<u8>>;
+
+    /// Creates or replaces file, the parent directory must exist
+    fn write_file(&self, file_path: &str, data: &[u8]) -> StorageResult<()>;
+
+    fn open_read_file(&self, file_path: &str) -> StorageResult<Box<dyn Read>> {
+        Ok(Box::new(Cursor::new(self.read_file(file_path)?)))
+    }
+}
+
+/// Validates path and splits it into names, the root directory gives an empty list
+pub fn split_path(path: &str) -> StorageResult<Vec<&str>> {
+    let invalid = || StorageError::InvalidPath(path.to_owned());
+
+    if path.len() > MAX_PATH_LEN || !path.starts_with('/') {
+        return Err(invalid());
+    }
+
+    if path == "/" {
+        return Ok(vec![]);
+    }
+
+    let names: Vec<&str> = path[1..].split('/').collect();
+    for name in names.iter() {
+        if name.is_empty()
+            || *name == "."
+            || *name == ".."
+            || name.chars().any(|x| x.is_control() || x == '\\' || x == ':')
+        {
+            return Err(invalid());
+        }
+    }
+
+    Ok(names)
+}
+
+/// Validates file path and splits it into the parent directory names and the file name
+pub fn split_file_path(file_path: &str) -> StorageResult<(Vec<&str>, &str)> {
+    let mut names = split_path(file_path)?;
+    match names.pop() {
+        Some(name) => Ok((names, name)),
+        None => Err(StorageError::InvalidPath(file_path.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_path_test() {
+        assert_eq!(split_path("/").unwrap(), Vec::<&str>::new());
+        assert_eq!(split_path("/a/b.txt").unwrap(), vec!["a", "b.txt"]);
+
+        assert!(split_path("").is_err());
+        assert!(split_path("a/b").is_err());
+        assert!(split_path("/a//b").is_err());
+        assert!(split_path("/a/").is_err());
+        assert!(split_path("/a/../b").is_err());
+        assert!(split_path("/a\\b").is_err());
+        assert!(split_path(&format!("/{}", "a".repeat(MAX_PATH_LEN))).is_err());
+
+        assert!(split_file_path("/").is_err());
+        assert_eq!(split_file_path("/a/b").unwrap(), (vec!["a"], "b"));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::device;
 use crate::tlv_parser;
 
 use std::ops::Deref;
@@ -5,6 +6,7 @@ use std::ops::Deref;
 use byteorder::{BigEndian, ByteOrder};
 use thiserror::Error;
 
+use device::TransactionType;
 use tlv_parser::{TagValue, TlvError};
 
 #[derive(Error, Debug)]
@@ -73,7 +75,7 @@ impl TagValue for U16BigEndianTagValue {
         Self: Sized,
     {
         Ok(Self {
-            val: BigEndian::read_u16(&raw),
+            val: u16::from_raw(raw)?,
         })
     }
 
@@ -112,7 +114,7 @@ impl TagValue for IntTagValue {
     {
         let mut str = String::new();
         for b in raw {
-            str.push_str(&format!("{:x}", b.clone() as u8))
+            str.push_str(&format!("{:02x}", b))
         }
 
         Ok(Self {
@@ -147,12 +149,74 @@ impl Deref for IntTagValue {
     }
 }
 
+/// Status/error codes returned by the reader in `FF03 / F2 / DF68`
+///
+/// Only the codes handled since the first version of the Uno8 driver are named,
+/// any other code is kept as `Unknown` and ends the transaction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AnnexE {
-    EmvTransactionTerminated = 0x09,
-    CollisionMoreThanOnePICCDetected = 0x06,
-    EmvTransactionTerminatedSeePhone = 0x29,
-    EmvTransactionTerminatedUseContactChannel = 0x2A,
-    EmvTransactionTerminatedTryAgain = 0x2B,
+    CollisionMoreThanOnePICCDetected,
+    EmvTransactionTerminated,
+    EmvTransactionTerminatedSeePhone,
+    EmvTransactionTerminatedUseContactChannel,
+    EmvTransactionTerminatedTryAgain,
+    Unknown(u8),
+}
+
+/// What the application should do after the reader returned an `AnnexE` code
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AnnexECategory {
+    /// The card should be presented again on the same interface
+    Retry,
+    /// The transaction may continue on another interface or with another card
+    SwitchInterface,
+    /// The transaction is over
+    Fatal,
+}
+
+impl AnnexE {
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x06 => AnnexE::CollisionMoreThanOnePICCDetected,
+            0x09 => AnnexE::EmvTransactionTerminated,
+            0x29 => AnnexE::EmvTransactionTerminatedSeePhone,
+            0x2A => AnnexE::EmvTransactionTerminatedUseContactChannel,
+            0x2B => AnnexE::EmvTransactionTerminatedTryAgain,
+            _ => AnnexE::Unknown(code),
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            AnnexE::CollisionMoreThanOnePICCDetected => 0x06,
+            AnnexE::EmvTransactionTerminated => 0x09,
+            AnnexE::EmvTransactionTerminatedSeePhone => 0x29,
+            AnnexE::EmvTransactionTerminatedUseContactChannel => 0x2A,
+            AnnexE::EmvTransactionTerminatedTryAgain => 0x2B,
+            AnnexE::Unknown(code) => *code,
+        }
+    }
+
+    pub fn category(&self) -> AnnexECategory {
+        match self {
+            AnnexE::CollisionMoreThanOnePICCDetected
+            | AnnexE::EmvTransactionTerminatedSeePhone
+            | AnnexE::EmvTransactionTerminatedTryAgain => AnnexECategory::Retry,
+            AnnexE::EmvTransactionTerminatedUseContactChannel => AnnexECategory::SwitchInterface,
+            AnnexE::EmvTransactionTerminated | AnnexE::Unknown(_) => AnnexECategory::Fatal,
+        }
+    }
+
+    /// Returns the standard message of EMV Contactless Book A, 9.4 to show to the cardholder
+    pub fn user_prompt(&self) -> &'static str {
+        match self {
+            AnnexE::CollisionMoreThanOnePICCDetected => "Please Present One Card Only",
+            AnnexE::EmvTransactionTerminatedSeePhone => "See Phone for Instructions",
+            AnnexE::EmvTransactionTerminatedUseContactChannel => "Please Insert or Swipe Card",
+            AnnexE::EmvTransactionTerminatedTryAgain => "Present Card Again",
+            AnnexE::EmvTransactionTerminated | AnnexE::Unknown(_) => "Processing Error",
+        }
+    }
 }
 
 pub struct AnnexETagValue {
@@ -170,38 +234,16 @@ impl TagValue for AnnexETagValue {
     where
         Self: Sized,
     {
-        if raw.len() > 1 {
-            return Err(TlvError::ParseTagValue(format!("expected 1 byte")));
+        match raw {
+            [code] => Ok(Self {
+                val: AnnexE::from_code(*code),
+            }),
+            _ => Err(TlvError::ParseTagValue("expected 1 byte".into())),
         }
-
-        Ok(Self {
-            val: match raw.first() {
-                Some(a) => match a {
-                    0x09 => AnnexE::EmvTransactionTerminated,
-                    0x06 => AnnexE::CollisionMoreThanOnePICCDetected,
-                    0x29 => AnnexE::EmvTransactionTerminatedSeePhone,
-                    0x2A => AnnexE::EmvTransactionTerminatedUseContactChannel,
-                    0x2B => AnnexE::EmvTransactionTerminatedTryAgain,
-                    _ => {
-                        return Err(TlvError::ParseTagValue(format!(
-                            "unknown AnnexE code {:02X}",
-                            a
-                        )))
-                    }
-                },
-                _ => return Err(TlvError::ParseTagValue(format!("expected 1 byte"))),
-            },
-        })
     }
 
     fn bytes(&self) -> Vec<u8> {
-        match self.val {
-            AnnexE::EmvTransactionTerminated => [0x09].to_vec(),
-            AnnexE::CollisionMoreThanOnePICCDetected => [0x06].to_vec(),
-            AnnexE::EmvTransactionTerminatedSeePhone => [0x29].to_vec(),
-            AnnexE::EmvTransactionTerminatedUseContactChannel => [0x2A].to_vec(),
-            AnnexE::EmvTransactionTerminatedTryAgain => [0x2B].to_vec(),
-        }
+        vec![self.val.code()]
     }
 }
 
@@ -212,15 +254,82 @@ impl Deref for AnnexETagValue {
     }
 }
 
+pub struct TransactionTypeTagValue {
+    val: TransactionType,
+}
+
+impl TagValue for TransactionTypeTagValue {
+    type Value = TransactionType;
+
+    fn new(val: Self::Value) -> Self {
+        Self { val }
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        match raw {
+            [code] => Ok(Self {
+                val: TransactionType::from_code(*code),
+            }),
+            _ => Err(TlvError::ParseTagValue("expected 1 byte".into())),
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        vec![self.val.code()]
+    }
+}
+
+impl Deref for TransactionTypeTagValue {
+    type Target = TransactionType;
+    fn deref(&self) -> &Self::Target {
+        &self.val
+    }
+}
+
+/// Bytes shown as uppercase hex digits without leading zeros, e.g. `[0x01, 0xAB]` as `1AB`
 pub struct HexTagValue {
     val: String,
+    raw: Vec<u8>,
+}
+
+impl HexTagValue {
+    /// Parses `val` of hex digits, an odd number of digits has an implied leading zero
+    pub fn parse(val: &str) -> Result<Self, TlvError> {
+        if val.is_empty() || !val.chars().all(|x| x.is_ascii_hexdigit()) {
+            return Err(TlvError::ParseTagValue(format!(
+                "expected hex digits: {:?}",
+                val
+            )));
+        }
+
+        let digits = match val.len() % 2 {
+            0 => val.to_owned(),
+            _ => format!("0{}", val),
+        };
+        let mut raw = vec![];
+        for i in (0..digits.len()).step_by(2) {
+            raw.push(u8::from_str_radix(&digits[i..i + 2], 16)?);
+        }
+
+        Ok(Self {
+            val: val.to_owned(),
+            raw,
+        })
+    }
 }
 
 impl TagValue for HexTagValue {
     type Value = String;
 
+    /// Panics if `val` is not hex digits, use `HexTagValue::parse` for untrusted input
     fn new(val: Self::Value) -> Self {
-        Self { val: val }
+        match Self::parse(&val) {
+            Ok(o) => o,
+            Err(e) => panic!("invalid HexTagValue: {}", e),
+        }
     }
 
     fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
@@ -229,17 +338,12 @@ impl TagValue for HexTagValue {
     {
         Ok(Self {
             val: raw.iter().map(|x| format!("{:X}", x)).collect(),
+            raw: raw.to_vec(),
         })
     }
 
     fn bytes(&self) -> Vec<u8> {
-        self.val
-            .chars()
-            .map(|x| x.to_digit(16).unwrap() as u8)
-            .collect::<Vec<u8>>()
-            .chunks(2)
-            .map(|x| (x[0] * 16) + x[1])
-            .collect()
+        self.raw.clone()
     }
 }
 
@@ -249,3 +353,388 @@ impl Deref for HexTagValue {
         &self.val
     }
 }
+
+fn check_len(raw: &[u8], expected: usize) -> Result<(), TlvError> {
+    if raw.len() != expected {
+        return Err(TlvError::ParseTagValue(format!(
+            "expected {} byte(s), found {}",
+            expected,
+            raw.len()
+        )));
+    }
+    Ok(())
+}
+
+impl TagValue for u8 {
+    type Value = u8;
+
+    fn new(val: Self::Value) -> Self {
+        val
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        check_len(raw, 1)?;
+        Ok(raw[0])
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        vec![*self]
+    }
+}
+
+impl TagValue for u16 {
+    type Value = u16;
+
+    fn new(val: Self::Value) -> Self {
+        val
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        check_len(raw, 2)?;
+        Ok(BigEndian::read_u16(raw))
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+impl TagValue for u32 {
+    type Value = u32;
+
+    fn new(val: Self::Value) -> Self {
+        val
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        check_len(raw, 4)?;
+        Ok(BigEndian::read_u32(raw))
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+impl TagValue for u64 {
+    type Value = u64;
+
+    fn new(val: Self::Value) -> Self {
+        val
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        check_len(raw, 8)?;
+        Ok(BigEndian::read_u64(raw))
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+/// One byte flag, any non-zero value is `true`
+impl TagValue for bool {
+    type Value = bool;
+
+    fn new(val: Self::Value) -> Self {
+        val
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        check_len(raw, 1)?;
+        Ok(raw[0] != 0x00)
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+}
+
+impl TagValue for Vec<u8> {
+    type Value = Vec<u8>;
+
+    fn new(val: Self::Value) -> Self {
+        val
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        Ok(raw.to_vec())
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl<const N: usize> TagValue for [u8; N] {
+    type Value = [u8; N];
+
+    fn new(val: Self::Value) -> Self {
+        val
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        check_len(raw, N)?;
+        let mut val = [0; N];
+        val.copy_from_slice(raw);
+        Ok(val)
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+/// Zero length value is decoded as `None`
+impl<T: TagValue> TagValue for Option<T> {
+    type Value = Option<T::Value>;
+
+    fn new(val: Self::Value) -> Self {
+        val.map(T::new)
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        if raw.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(T::from_raw(raw)?))
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Some(val) => val.bytes(),
+            None => vec![],
+        }
+    }
+}
+
+/// EMV format `an`: alphabetic (a-z, A-Z) and numeric (0-9) characters
+pub struct AlphaNumericTagValue {
+    val: String,
+}
+
+impl AlphaNumericTagValue {
+    pub fn is_valid(val: &str) -> bool {
+        val.chars().all(|x| x.is_ascii_alphanumeric())
+    }
+}
+
+impl TagValue for AlphaNumericTagValue {
+    type Value = String;
+
+    fn new(val: Self::Value) -> Self {
+        Self { val }
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        let val = String::from_utf8(raw.to_vec())?;
+        if !Self::is_valid(&val) {
+            return Err(TlvError::ParseTagValue(format!(
+                "expected alphanumeric (an) value: {:02X?}",
+                raw
+            )));
+        }
+        Ok(Self { val })
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.val.bytes().collect()
+    }
+}
+
+impl Deref for AlphaNumericTagValue {
+    type Target = String;
+    fn deref(&self) -> &Self::Target {
+        &self.val
+    }
+}
+
+/// EMV format `ans`: printable characters of the ISO/IEC 8859 common character set
+pub struct AlphaNumericSpecialTagValue {
+    val: String,
+}
+
+impl AlphaNumericSpecialTagValue {
+    pub fn is_valid(val: &str) -> bool {
+        val.chars().all(|x| (' '..='~').contains(&x))
+    }
+}
+
+impl TagValue for AlphaNumericSpecialTagValue {
+    type Value = String;
+
+    fn new(val: Self::Value) -> Self {
+        Self { val }
+    }
+
+    fn from_raw(raw: &[u8]) -> Result<Self, TlvError>
+    where
+        Self: Sized,
+    {
+        let val = String::from_utf8(raw.to_vec())?;
+        if !Self::is_valid(&val) {
+            return Err(TlvError::ParseTagValue(format!(
+                "expected alphanumeric special (ans) value: {:02X?}",
+                raw
+            )));
+        }
+        Ok(Self { val })
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.val.bytes().collect()
+    }
+}
+
+impl Deref for AlphaNumericSpecialTagValue {
+    type Target = String;
+    fn deref(&self) -> &Self::Target {
+        &self.val
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsigned_test() {
+        assert_eq!(u8::from_raw(&[0x12]).unwrap(), 0x12);
+        assert_eq!(u16::from_raw(&[0x12, 0x34]).unwrap(), 0x1234);
+        assert_eq!(u32::from_raw(&[0x12, 0x34, 0x56, 0x78]).unwrap(), 0x12345678);
+        assert_eq!(
+            u64::from_raw(&[0x00, 0x00, 0x00, 0x00, 0x12, 0x34, 0x56, 0x78]).unwrap(),
+            0x12345678
+        );
+        assert_eq!(0x1234_u16.bytes(), vec![0x12, 0x34]);
+        assert_eq!(0x12345678_u32.bytes(), vec![0x12, 0x34, 0x56, 0x78]);
+
+        assert!(u16::from_raw(&[0x12]).is_err());
+        assert!(u32::from_raw(&[0x12, 0x34, 0x56]).is_err());
+        assert!(u64::from_raw(&[]).is_err());
+        assert!(U16BigEndianTagValue::from_raw(&[0x01]).is_err());
+    }
+
+    #[test]
+    fn container_test() {
+        assert!(bool::from_raw(&[0x01]).unwrap());
+        assert!(!bool::from_raw(&[0x00]).unwrap());
+        assert!(bool::from_raw(&[]).is_err());
+
+        assert_eq!(<[u8; 3]>::from_raw(&[1, 2, 3]).unwrap(), [1, 2, 3]);
+        assert!(<[u8; 3]>::from_raw(&[1, 2]).is_err());
+
+        assert_eq!(Option::<u16>::from_raw(&[]).unwrap(), None);
+        assert_eq!(Option::<u16>::from_raw(&[0x00, 0x01]).unwrap(), Some(1));
+        assert_eq!(Option::<u16>::new(None).bytes(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn string_format_test() {
+        assert_eq!(*AlphaNumericTagValue::from_raw(b"TID00001").unwrap(), "TID00001");
+        assert!(AlphaNumericTagValue::from_raw(b"TID 0001").is_err());
+
+        assert_eq!(
+            *AlphaNumericSpecialTagValue::from_raw(b"Shop #1").unwrap(),
+            "Shop #1"
+        );
+        assert!(AlphaNumericSpecialTagValue::from_raw(&[0x41, 0x0A]).is_err());
+    }
+
+    #[test]
+    fn annex_e_test() {
+        for code in 0..=0xFF {
+            assert_eq!(AnnexE::from_code(code).code(), code);
+        }
+        assert_eq!(AnnexE::from_code(0x2B), AnnexE::EmvTransactionTerminatedTryAgain);
+        assert_eq!(AnnexE::from_code(0x01), AnnexE::Unknown(0x01));
+
+        assert_eq!(
+            AnnexE::CollisionMoreThanOnePICCDetected.category(),
+            AnnexECategory::Retry
+        );
+        assert_eq!(
+            AnnexE::EmvTransactionTerminatedUseContactChannel.category(),
+            AnnexECategory::SwitchInterface
+        );
+        assert_eq!(AnnexE::Unknown(0x7F).category(), AnnexECategory::Fatal);
+
+        let val = AnnexETagValue::from_raw(&[0x42]).unwrap();
+        assert_eq!(*val, AnnexE::Unknown(0x42));
+        assert_eq!(val.bytes(), vec![0x42]);
+        assert!(AnnexETagValue::from_raw(&[]).is_err());
+    }
+
+    #[test]
+    fn transaction_type_test() {
+        let val = TransactionTypeTagValue::from_raw(&[0x20]).unwrap();
+        assert_eq!(*val, TransactionType::Refund);
+        assert_eq!(
+            TransactionTypeTagValue::new(TransactionType::PurchaseWithCashback).bytes(),
+            vec![0x09]
+        );
+        assert_eq!(
+            *TransactionTypeTagValue::from_raw(&[0x7F]).unwrap(),
+            TransactionType::Other(0x7F)
+        );
+        assert!(TransactionTypeTagValue::from_raw(&[]).is_err());
+        assert!(TransactionTypeTagValue::from_raw(&[0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn hex_test() {
+        // serial numbers built from this string are stored by applications
+        let val = HexTagValue::from_raw(&[0x01, 0xAB, 0x00, 0x0C]).unwrap();
+        assert_eq!(*val, "1AB0C");
+        assert_eq!(val.bytes(), vec![0x01, 0xAB, 0x00, 0x0C]);
+
+        assert_eq!(HexTagValue::new("1AB".into()).bytes(), vec![0x01, 0xAB]);
+        assert!(HexTagValue::parse("1AG").is_err());
+        assert!(HexTagValue::parse("+1").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid HexTagValue")]
+    fn hex_new_invalid_test() {
+        HexTagValue::new("+1".into());
+    }
+
+    #[test]
+    fn int_test() {
+        let val = IntTagValue::from_raw(&[0x01, 0x05]).unwrap();
+        assert_eq!(*val, 105);
+        assert_eq!(val.bytes(), vec![0x01, 0x05]);
+
+        let amount = IntTagValue::from_raw(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00]).unwrap();
+        assert_eq!(*amount, 1000);
+        assert_eq!(amount.bytes(), vec![0x00, 0x00, 0x00, 0x00, 0x10, 0x00]);
+    }
+}
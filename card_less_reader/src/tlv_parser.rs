@@ -204,6 +204,11 @@ impl Tlv {
         &self.val
     }
 
+    /// Returns value of TLV, consuming it
+    pub fn into_val(self) -> Value {
+        self.val
+    }
+
     /// Returns TLV-encoded array of bytes
     ///
     /// # Examples
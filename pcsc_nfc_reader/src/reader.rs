@@ -0,0 +1,246 @@
+use std::ffi::CString;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use card_less_reader::{
+    apdu::{CommandApdu, ResponseApdu},
+    card::{CardAccess, CardInfo, CardTechnology, CardType, PollCardResult},
+    device::CancellationToken,
+    error::DeviceError,
+};
+use pcsc::{
+    Card, Context, Disposition, Protocols, ReaderState, Scope, ShareMode, State,
+    MAX_BUFFER_SIZE_EXTENDED,
+};
+
+/// How long `activate` waits for a reader event before checking the cancellation token
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// RID of PC/SC Workgroup in the ATR of a storage card, PC/SC Part 3 3.1.3.2.3.2
+const PCSC_RID: [u8; 5] = [0xA0, 0x00, 0x00, 0x03, 0x06];
+
+/// Contactless PC/SC reader, e.g. ACS ACR122U or HID OMNIKEY 5022
+///
+/// The reader driver handles the pseudo-APDUs of PC/SC Part 3, so MIFARE cards are
+/// accessed by `card_less_reader::mifare::PcscMemoryCard` on top of it.
+pub struct PcscReader {
+    context: Context,
+    reader: CString,
+    card: Mutex<Option<Card>>,
+}
+
+impl PcscReader {
+    /// Names of the readers known to the PC/SC service
+    pub fn list_readers() -> Result<Vec<String>, DeviceError> {
+        let context = Context::establish(Scope::User).map_err(pcsc_error)?;
+        Ok(context
+            .list_readers_owned()
+            .map_err(pcsc_error)?
+            .into_iter()
+            .map(|reader| reader.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    pub fn open(reader: &str) -> Result<Self, DeviceError> {
+        let name = CString::new(reader)
+            .map_err(|_| DeviceError::Other(format!("invalid reader name {:?}", reader)))?;
+        Ok(Self {
+            context: Context::establish(Scope::User).map_err(pcsc_error)?,
+            reader: name,
+            card: Mutex::new(None),
+        })
+    }
+
+    /// Waits until a responsive card is in the field, `false` if cancelled
+    fn wait_card(&self, cancel: &CancellationToken) -> Result<bool, DeviceError> {
+        let mut states = [ReaderState::new(self.reader.clone(), State::UNAWARE)];
+        loop {
+            if cancel.is_cancelled() {
+                return Ok(false);
+            }
+            let timeout = match cancel.remaining() {
+                Some(remaining) => remaining.min(POLL_INTERVAL),
+                None => POLL_INTERVAL,
+            };
+            match self.context.get_status_change(timeout, &mut states) {
+                Ok(()) => {}
+                Err(pcsc::Error::Timeout) => continue,
+                Err(e) => return Err(pcsc_error(e)),
+            }
+
+            let state = states[0].event_state();
+            if state.intersects(State::UNKNOWN | State::UNAVAILABLE) {
+                return Err(DeviceError::MessageChannel(format!(
+                    "reader {:?} is not available",
+                    self.reader
+                )));
+            }
+            if state.contains(State::PRESENT) && !state.contains(State::MUTE) {
+                return Ok(true);
+            }
+            states[0].sync_current_state();
+        }
+    }
+
+    fn transmit(card: &Card, command: &[u8]) -> Result<ResponseApdu, DeviceError> {
+        let mut buffer = vec![0; MAX_BUFFER_SIZE_EXTENDED];
+        let response = card.transmit(command, &mut buffer).map_err(pcsc_error)?;
+        Ok(ResponseApdu::from_bytes(response)?)
+    }
+}
+
+impl CardAccess for PcscReader {
+    /// Polls the reader until a card is present, a card activated before is disconnected
+    ///
+    /// Cancellation is checked every 100 ms.
+    fn activate(&self, cancel: &CancellationToken) -> Result<PollCardResult, DeviceError> {
+        self.deactivate()?;
+
+        loop {
+            if !self.wait_card(cancel)? {
+                return Ok(PollCardResult::Canceled);
+            }
+
+            let card = match self
+                .context
+                .connect(&self.reader, ShareMode::Shared, Protocols::ANY)
+            {
+                Ok(card) => card,
+                // Removed before it was connected, keep polling
+                Err(pcsc::Error::NoSmartcard)
+                | Err(pcsc::Error::RemovedCard)
+                | Err(pcsc::Error::UnresponsiveCard) => continue,
+                Err(e) => return Err(pcsc_error(e)),
+            };
+
+            let atr = card.status2_owned().map_err(pcsc_error)?.atr().to_vec();
+            // GET DATA of UID, PC/SC Part 3 3.2.2.1.3
+            let uid = Self::transmit(&card, &[0xFF, 0xCA, 0x00, 0x00, 0x00])?;
+            if !uid.is_success() {
+                return Err(DeviceError::CardStatus(uid.sw));
+            }
+            let info = card_info(&atr, uid.data).ok_or_else(|| {
+                DeviceError::Other(format!("card with ATR {:02X?} is not supported", atr))
+            })?;
+
+            *self.card.lock().unwrap() = Some(card);
+            return Ok(PollCardResult::Card(info));
+        }
+    }
+
+    fn transceive(&self, command: &CommandApdu) -> Result<ResponseApdu, DeviceError> {
+        let card = self.card.lock().unwrap();
+        let card = card
+            .as_ref()
+            .ok_or_else(|| DeviceError::Other("card is not activated".into()))?;
+        Self::transmit(card, &command.to_vec()?)
+    }
+
+    fn deactivate(&self) -> Result<(), DeviceError> {
+        if let Some(card) = self.card.lock().unwrap().take() {
+            card.disconnect(Disposition::UnpowerCard)
+                .map_err(|(_, e)| pcsc_error(e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Identifies a card by the ATR the reader builds for it, PC/SC Part 3 3.1.3.2.3
+///
+/// ISO/IEC 14443-4 cards are reported as Type A, their ATR does not tell Type B apart.
+/// `None` for cards of other standards, e.g. ISO/IEC 15693.
+fn card_info(atr: &[u8], uid: Vec<u8>) -> Option<CardInfo> {
+    let (technology, card_type) = match atr {
+        // Storage card: standard and card name after the RID
+        [0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, rid @ .., ss, c0, c1, _, _, _, _, _]
+            if rid == PCSC_RID =>
+        {
+            let technology = match ss {
+                0x03 => CardTechnology::IsoA,
+                0x07 => CardTechnology::IsoB,
+                0x11 => CardTechnology::Felica,
+                _ => return None,
+            };
+            let card_type = match u16::from_be_bytes([*c0, *c1]) {
+                0x0001 => CardType::MifareClassic1K,
+                0x0002 => CardType::MifareClassic4K,
+                0x0003 | 0x003A | 0x003D => CardType::MifareUltralight,
+                0x0026 => CardType::MifareMini,
+                0x0036..=0x0039 => CardType::MifarePlus,
+                0x003B => CardType::Felica,
+                _ => CardType::Unknown,
+            };
+            (technology, card_type)
+        }
+        // ISO/IEC 14443-4 card: historical bytes of ATS or ATQB
+        [0x3B, t0, 0x80, 0x01, ..] if t0 & 0xF0 == 0x80 => (CardTechnology::IsoA, CardType::IsoDep),
+        _ => return None,
+    };
+
+    Some(CardInfo {
+        technology,
+        uid,
+        atqa: None,
+        sak: None,
+        atqb: None,
+        ats: None,
+        card_type,
+    })
+}
+
+fn pcsc_error(error: pcsc::Error) -> DeviceError {
+    match error {
+        pcsc::Error::Timeout => DeviceError::Timeout("PC/SC".into()),
+        pcsc::Error::Cancelled => DeviceError::OperationCanceled,
+        _ => DeviceError::MessageChannel(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_info_test() {
+        let uid = vec![0x04, 0x01, 0x02, 0x03];
+
+        let classic = [
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x03, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x6A,
+        ];
+        let info = card_info(&classic, uid.clone()).unwrap();
+        assert_eq!(info.technology, CardTechnology::IsoA);
+        assert_eq!(info.card_type, CardType::MifareClassic1K);
+        assert_eq!(info.uid, uid);
+
+        let ultralight = [
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x03, 0x00,
+            0x03, 0x00, 0x00, 0x00, 0x00, 0x68,
+        ];
+        let info = card_info(&ultralight, uid.clone()).unwrap();
+        assert_eq!(info.card_type, CardType::MifareUltralight);
+
+        let felica = [
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ];
+        let info = card_info(&felica, uid.clone()).unwrap();
+        assert_eq!(info.technology, CardTechnology::Felica);
+        assert_eq!(info.card_type, CardType::Felica);
+
+        let desfire = [0x3B, 0x81, 0x80, 0x01, 0x80, 0x80];
+        let info = card_info(&desfire, uid.clone()).unwrap();
+        assert_eq!(info.technology, CardTechnology::IsoA);
+        assert_eq!(info.card_type, CardType::IsoDep);
+
+        // ISO/IEC 15693
+        let icode = [
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x0B, 0x00,
+            0x14, 0x00, 0x00, 0x00, 0x00, 0x71,
+        ];
+        assert!(card_info(&icode, uid.clone()).is_none());
+
+        // Contact card
+        assert!(card_info(&[0x3B, 0x02, 0x14, 0x50], uid).is_none());
+    }
+}
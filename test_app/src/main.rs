@@ -1,17 +1,26 @@
-use card_less_reader::{error::DeviceError, device::*};
+use card_less_reader::{
+    capk::CapkStore,
+    card::{CardAccess, PollCardResult},
+    clock::{ClockDrift, DateTime},
+    config::Configuration,
+    device::*,
+    device_info::{CapabilitySet, DeviceInfo},
+    display_message::Language,
+    error::DeviceError,
+    event::{DeviceEvent, EventSubscription},
+    storage::Storage,
+};
 use uno8_nfc_reader::{device::Uno8NfcDevice, device_builder::Uno8NfcDeviceBuilder};
 
 use cursive::menu::MenuTree;
 use cursive::traits::*;
-use cursive::views::{Dialog, EditView, RadioGroup};
+use cursive::views::{Dialog, EditView, RadioGroup, SelectView};
 use cursive::views::{LinearLayout, TextView};
 use cursive::Cursive;
 
 use std::sync::{Arc, Mutex};
-use std::{
-    sync::atomic::{AtomicBool, Ordering},
-    thread,
-};
+use std::thread;
+use std::time::Duration;
 
 struct StubDevice;
 
@@ -22,7 +31,7 @@ impl CardLessDevice for StubDevice {
     fn poll_emv(
         &mut self,
         purchase: Option<PollEmvPurchase>,
-        cancel_flag: Arc<AtomicBool>,
+        cancel: &CancellationToken,
     ) -> Result<PollEmvResult, card_less_reader::error::DeviceError> {
         todo!()
     }
@@ -55,15 +64,72 @@ impl CardLessDevice for Device {
             Device::Stub(d) => d.get_sn()
         }
     }
+
+    fn device_info(&mut self) -> Result<DeviceInfo, DeviceError> {
+        match self {
+            Device::Uno8(d) => d.device_info(),
+            Device::Stub(d) => d.device_info(),
+        }
+    }
+
+    fn capabilities(&mut self) -> CapabilitySet {
+        match self {
+            Device::Uno8(d) => d.capabilities(),
+            Device::Stub(d) => d.capabilities(),
+        }
+    }
+
+    fn get_time(&mut self) -> Result<DateTime, DeviceError> {
+        match self {
+            Device::Uno8(d) => d.get_time(),
+            Device::Stub(d) => d.get_time(),
+        }
+    }
+
+    fn set_time(&mut self, time: &DateTime) -> Result<(), DeviceError> {
+        match self {
+            Device::Uno8(d) => d.set_time(time),
+            Device::Stub(d) => d.set_time(time),
+        }
+    }
+
+    fn sync_clock(
+        &mut self,
+        host_time: DateTime,
+        max_drift: Duration,
+    ) -> Result<ClockDrift, DeviceError> {
+        match self {
+            Device::Uno8(d) => d.sync_clock(host_time, max_drift),
+            Device::Stub(d) => d.sync_clock(host_time, max_drift),
+        }
+    }
     
     fn poll_emv(
         &mut self,
         purchase: Option<PollEmvPurchase>,
-        cancel_flag: Arc<AtomicBool>,
+        cancel: &CancellationToken,
     ) -> Result<PollEmvResult, card_less_reader::error::DeviceError> {
         match self {
-            Device::Uno8(d) => d.poll_emv(purchase, cancel_flag),
-            Device::Stub(d) => d.poll_emv(purchase, cancel_flag)
+            Device::Uno8(d) => d.poll_emv(purchase, cancel),
+            Device::Stub(d) => d.poll_emv(purchase, cancel)
+        }
+    }
+
+    fn poll_card(&mut self, cancel: &CancellationToken) -> Result<PollCardResult, DeviceError> {
+        match self {
+            Device::Uno8(d) => d.poll_card(cancel),
+            Device::Stub(d) => d.poll_card(cancel),
+        }
+    }
+
+    fn transaction(
+        &mut self,
+        request: TransactionRequest,
+        cancel: &CancellationToken,
+    ) -> Result<PollEmvResult, DeviceError> {
+        match self {
+            Device::Uno8(d) => d.transaction(request, cancel),
+            Device::Stub(d) => d.transaction(request, cancel),
         }
     }
 
@@ -74,16 +140,53 @@ impl CardLessDevice for Device {
         }
     }
 
+    fn card_access(&mut self) -> Option<&dyn CardAccess> {
+        match self {
+            Device::Uno8(d) => d.card_access(),
+            Device::Stub(d) => d.card_access(),
+        }
+    }
+
+    fn configuration(&mut self) -> Option<&dyn Configuration> {
+        match self {
+            Device::Uno8(d) => d.configuration(),
+            Device::Stub(d) => d.configuration(),
+        }
+    }
+
+    fn capk_store(&mut self) -> Option<&dyn CapkStore> {
+        match self {
+            Device::Uno8(d) => d.capk_store(),
+            Device::Stub(d) => d.capk_store(),
+        }
+    }
+
+    fn user_interface(&mut self) -> Option<&dyn UserInterface> {
+        match self {
+            Device::Uno8(d) => d.user_interface(),
+            Device::Stub(d) => d.user_interface(),
+        }
+    }
+
     fn storage(&mut self) -> Option<&dyn Storage> {
         match self {
             Device::Uno8(d) => d.storage(),
             Device::Stub(d) => d.storage(),
         }
     }
+
+    fn subscribe(&self) -> EventSubscription {
+        match self {
+            Device::Uno8(d) => d.subscribe(),
+            Device::Stub(d) => d.subscribe(),
+        }
+    }
 }
 
 struct Session {
     device: Mutex<Device>,
+    /// Language of display messages, English until the outcome brings the cardholder preference
+    language: Arc<Mutex<Language>>,
 }
 
 fn main() {
@@ -145,23 +248,40 @@ fn connection(view: &mut Cursive) {
 
                 match Uno8NfcDeviceBuilder::use_hid(vid, pid) {
                     Ok(o) => {
-                        let device = o
-                            .set_external_display(move |x| {
-                                let message = format!("{}", x);
-                                cb_sink
-                                    .send(Box::new(move |y: &mut cursive::Cursive| {
-                                        y.call_on_name("external_display", |d: &mut TextView| {
-                                            d.set_content(message)
-                                        });
-                                    }))
-                                    .unwrap()
-                            })
-                            .set_internal_log(|x| log::info!("InternalLog: {}", x))
-                            .set_card_removal(|| log::info!("CardRemoval"))
-                            .finish();
+                        let device = o.finish();
+
+                        let language = Arc::new(Mutex::new(Language::English));
+                        let language_ref = language.clone();
+
+                        let events = device.subscribe();
+                        thread::spawn(move || {
+                            for record in events {
+                                match record.event {
+                                    DeviceEvent::DisplayMessage(message) => {
+                                        let text = message
+                                            .localized(*language_ref.lock().unwrap())
+                                            .into_owned();
+                                        cb_sink
+                                            .send(Box::new(move |y: &mut cursive::Cursive| {
+                                                y.call_on_name("external_display", |d: &mut TextView| {
+                                                    d.set_content(text)
+                                                });
+                                            }))
+                                            .unwrap()
+                                    }
+                                    DeviceEvent::InternalLog(x) => log::info!("InternalLog: {}", x),
+                                    DeviceEvent::CardRemoved => log::info!("CardRemoval"),
+                                    DeviceEvent::LanguagePreference(preference) => {
+                                        *language_ref.lock().unwrap() =
+                                            Language::from_preference(&preference)
+                                    }
+                                }
+                            }
+                        });
 
                         x.set_user_data(Arc::new(Session {
                             device: Mutex::new(Device::Uno8(device)),
+                            language,
                         }));
 
                         x.pop_layer();
@@ -184,6 +304,7 @@ fn home(view: &mut Cursive) {
 
     let mut commands = MenuTree::new();
     commands.add_leaf("Get serial number", |x| get_sn_cmd(x));
+    commands.add_leaf("Device info", |x| device_info_cmd(x));
 
     if let Some(_) = device.ext_display() {
         commands.add_leaf("External display mode", |x| ext_display_mode_cmd(x));
@@ -209,6 +330,38 @@ fn get_sn_cmd(view: &mut Cursive) {
     };
 }
 
+fn device_info_cmd(view: &mut Cursive) {
+    let session = view.user_data::<Arc<Session>>().unwrap().clone();
+    let mut device = session.device.lock().unwrap();
+
+    let info = match device.device_info() {
+        Ok(info) => info,
+        Err(e) => {
+            view.add_layer(Dialog::info(format!("{}", e)));
+            return;
+        }
+    };
+
+    let mut text = format!(
+        "Model: {}\nSerial number: {}\nFirmware: {}\nBootloader: {}\n",
+        info.model,
+        info.serial_number,
+        info.firmware_version,
+        info.bootloader_version.as_deref().unwrap_or("-")
+    );
+    for kernel in &info.kernels {
+        text.push_str(&format!("Kernel {}: {}\n", kernel.name(), kernel.version));
+    }
+    text.push_str(&format!("Interfaces: {:?}\n", info.interfaces));
+    text.push_str(&format!("Hardware: {:?}\n", info.hardware_options));
+    text.push_str(&format!(
+        "Capabilities: {:?}",
+        device.capabilities().iter().collect::<Vec<_>>()
+    ));
+
+    view.add_layer(Dialog::info(text).title("Device info"));
+}
+
 fn ext_display_mode_cmd(view: &mut Cursive) {
     let mut mode: RadioGroup<ExtDisplayMode> = RadioGroup::new();
 
@@ -269,13 +422,19 @@ fn poll_emv(view: &mut Cursive) {
                 LinearLayout::vertical()
                     .child(
                         LinearLayout::horizontal()
-                            .child(TextView::new("Payment type: "))
+                            .child(TextView::new("Type         :"))
                             .child(
-                                EditView::new()
-                                    .content("1")
-                                    .max_content_width(1)
-                                    .with_name("p_type")
-                                    .fixed_width(1),
+                                SelectView::new()
+                                    .popup()
+                                    .item("Purchase", TransactionType::Purchase)
+                                    .item("Cash", TransactionType::Cash)
+                                    .item(
+                                        "Purchase with cashback",
+                                        TransactionType::PurchaseWithCashback,
+                                    )
+                                    .item("Refund", TransactionType::Refund)
+                                    .item("Balance inquiry", TransactionType::BalanceInquiry)
+                                    .with_name("transaction_type"),
                             ),
                     )
                     .child(
@@ -299,25 +458,22 @@ fn poll_emv(view: &mut Cursive) {
                                     .with_name("amount")
                                     .fixed_width(12),
                             ),
+                    )
+                    .child(
+                        LinearLayout::horizontal()
+                            .child(TextView::new("Amount other :"))
+                            .child(
+                                EditView::new()
+                                    .max_content_width(12)
+                                    .with_name("amount_other")
+                                    .fixed_width(12),
+                            ),
                     ),
             )
             .button("Ok", |x| {
-                let cancel_flag = Arc::new(AtomicBool::new(false));
-                let cancel_flag_ref = cancel_flag.clone();
-
-                x.add_layer(
-                    Dialog::new()
-                        .title("Waiting card")
-                        .content(TextView::new("").with_name("external_display"))
-                        .button("Cancel", move |y| {
-                            cancel_flag.store(true, Ordering::SeqCst);
-                            y.pop_layer();
-                        }),
-                );
-
-                let p_type = x
-                    .call_on_name("p_type", |y: &mut EditView| {
-                        u8::from_str_radix(&y.get_content(), 16).unwrap()
+                let transaction_type = x
+                    .call_on_name("transaction_type", |y: &mut SelectView<TransactionType>| {
+                        *y.selection().unwrap()
                     })
                     .unwrap();
                 let currency_code = x
@@ -332,23 +488,62 @@ fn poll_emv(view: &mut Cursive) {
                     .parse()
                     .unwrap();
 
+                let amount_other = x
+                    .call_on_name("amount_other", |y: &mut EditView| y.get_content())
+                    .unwrap();
+
+                let mut purchase = PollEmvPurchase::new(transaction_type, currency_code, amount);
+                if !amount_other.is_empty() {
+                    match amount_other.parse() {
+                        Ok(amount_other) => purchase = purchase.with_amount_other(amount_other),
+                        Err(_) => {
+                            x.add_layer(Dialog::info("Amount other must be a number"));
+                            return;
+                        }
+                    }
+                }
+
+                let cancel = CancellationToken::new();
+                let cancel_ref = cancel.clone();
+
+                x.add_layer(
+                    Dialog::new()
+                        .title("Waiting card")
+                        .content(TextView::new("").with_name("external_display"))
+                        .button("Cancel", move |y| {
+                            cancel.cancel();
+                            y.pop_layer();
+                        }),
+                );
+
                 let session = x.user_data::<Arc<Session>>().unwrap().clone();
                 let sb_sink = x.cb_sink().clone();
 
                 thread::spawn(move || {
                     let mut device = session.device.lock().unwrap();
 
+                    // The preference of the previous cardholder must not leak into this transaction
+                    *session.language.lock().unwrap() = Language::English;
+
                     match device.poll_emv(
-                        Some(PollEmvPurchase::new(p_type, currency_code, amount)),
-                        cancel_flag_ref,
+                        Some(purchase),
+                        &cancel_ref,
                     ) {
                         Ok(o) => match o {
                             PollEmvResult::Canceled => {}
-                            PollEmvResult::Success(tlv) => {
+                            PollEmvResult::Outcome(outcome) => {
+                                let prompt = match &outcome.ui_request {
+                                    Some(ui) => format!("{}\n", ui.message),
+                                    None => String::new(),
+                                };
+                                let text = format!(
+                                    "{:?}, CVM: {:?}, receipt: {}\n{}{}",
+                                    outcome.kind, outcome.cvm, outcome.receipt, prompt, outcome.tlv
+                                );
                                 sb_sink
                                     .send(Box::new(move |x: &mut cursive::Cursive| {
                                         x.pop_layer();
-                                        x.add_layer(Dialog::info(format!("{}", tlv)))
+                                        x.add_layer(Dialog::info(text))
                                     }))
                                     .unwrap();
                             }
@@ -3,16 +3,22 @@ use crate::message_channel;
 use crate::tag_value;
 
 use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc, Mutex,
-};
-use std::{thread, time::Duration};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use card_less_reader::{
+    config::{ConfigScope, Configuration, TerminalConfig, TAG_DICTIONARY},
     device::*,
+    display_message::DisplayMessage,
     error::*,
-    tag_value::{AnnexE, AnnexETagValue, IntTagValue, StringAsciiTagValue, U16BigEndianTagValue},
+    event::{DeviceEvent, EventBus, EventSubscription},
+    outcome::{Cvm, OutcomeKind, PollOutcome, UiRequest},
+    storage::Storage,
+    tag_value::{
+        AnnexE, AnnexETagValue, IntTagValue, StringAsciiTagValue,
+        TransactionTypeTagValue,
+    },
     tlv_parser::{TagValue, Tlv, Value},
 };
 
@@ -20,21 +26,50 @@ use error::*;
 use message_channel::{MessageChannel, ReadMessage, WriteMessage};
 use tag_value::{ExtDisplayModeTagValue, SerialNumberTagValue};
 
-struct NotifyCallbacks {
-    external_display: Option<Box<dyn Fn(&String) + Send>>,
-    internal_log: Option<Box<dyn Fn(&String) + Send>>,
-    card_removal: Option<Box<dyn Fn() + Send>>,
+/// Preset messages of `ExtDisplayMode::Simple` by their index in `DF46`
+///
+/// The reader numbers them by Message Identifier of EMV Contactless Book A, 9.4,
+/// indices which are not listed are decoded as `DisplayMessage::Unknown`.
+const SIMPLE_MESSAGES: &[(u8, DisplayMessage)] = &[
+    (0x03, DisplayMessage::Approved),
+    (0x07, DisplayMessage::NotAuthorised),
+    (0x09, DisplayMessage::EnterPin),
+    (0x0F, DisplayMessage::ProcessingError),
+    (0x10, DisplayMessage::RemoveCard),
+    (0x14, DisplayMessage::Welcome),
+    (0x15, DisplayMessage::PresentCard),
+    (0x16, DisplayMessage::Processing),
+    (0x17, DisplayMessage::CardReadOk),
+    (0x18, DisplayMessage::InsertOrSwipeCard),
+    (0x19, DisplayMessage::PresentOneCardOnly),
+    (0x1A, DisplayMessage::ApprovedPleaseSign),
+    (0x1B, DisplayMessage::AuthorisingPleaseWait),
+    (0x1C, DisplayMessage::TryAnotherCard),
+    (0x1D, DisplayMessage::InsertCard),
+    (0x1E, DisplayMessage::ClearDisplay),
+    (0x20, DisplayMessage::SeePhone),
+    (0x21, DisplayMessage::PresentCardAgain),
+];
+
+enum ReadOut {
+    Message(Result<ReadMessage, ReadMessageError>),
+    /// Wakes up a cancellable read
+    Wake,
 }
 
 pub struct Uno8NfcDevice {
     write_in: Sender<(WriteMessage, Sender<Result<(), WriteMessageError>>)>,
-    read_out: Receiver<Result<ReadMessage, ReadMessageError>>,
+    read_out: Receiver<ReadOut>,
+    /// Weak so that `read_out` gets disconnected when the channel thread is gone
+    read_wake: Weak<Sender<ReadOut>>,
 
     write_timeout: Duration,
     read_timeout: Duration,
     ask_timeout: Duration,
 
-    notify_callbacks: Arc<Mutex<NotifyCallbacks>>,
+    events: Arc<EventBus>,
+    /// Last mode read from or written to the reader, decides how `DF46` messages are decoded
+    display_mode: Arc<Mutex<Option<ExtDisplayMode>>>,
 }
 
 impl Uno8NfcDevice {
@@ -42,35 +77,45 @@ impl Uno8NfcDevice {
         let (write_in_tx, write_in_rx) = mpsc::channel();
         let (read_out_tx, read_out_rx) = mpsc::channel();
 
-        let notify_callbacks = Arc::new(Mutex::new(NotifyCallbacks {
-            external_display: None,
-            internal_log: None,
-            card_removal: None,
-        }));
+        let events = Arc::new(EventBus::new());
+
+        let display_mode = Arc::new(Mutex::new(None));
 
-        let notify_callbacks_ref = notify_callbacks.clone();
+        let events_ref = events.clone();
+        let display_mode_ref = display_mode.clone();
+        let read_out_tx = Arc::new(read_out_tx);
+        let read_wake = Arc::downgrade(&read_out_tx);
 
         thread::spawn(move || {
-            Self::channel_loop(channel, notify_callbacks_ref, write_in_rx, read_out_tx)
+            Self::channel_loop(
+                channel,
+                events_ref,
+                display_mode_ref,
+                write_in_rx,
+                read_out_tx,
+            )
         });
 
         Self {
             write_in: write_in_tx,
             read_out: read_out_rx,
+            read_wake,
 
             write_timeout: Duration::from_millis(30),
             read_timeout: Duration::from_millis(1500),
             ask_timeout: Duration::from_millis(30),
 
-            notify_callbacks: notify_callbacks,
+            events,
+            display_mode,
         }
     }
 
     fn channel_loop(
         channel: impl MessageChannel,
-        notify_callbacks: Arc<Mutex<NotifyCallbacks>>,
+        events: Arc<EventBus>,
+        display_mode: Arc<Mutex<Option<ExtDisplayMode>>>,
         write_in: Receiver<(WriteMessage, Sender<Result<(), WriteMessageError>>)>,
-        read_out: Sender<Result<ReadMessage, ReadMessageError>>,
+        read_out: Arc<Sender<ReadOut>>,
     ) {
         loop {
             match write_in.try_recv() {
@@ -86,39 +131,37 @@ impl Uno8NfcDevice {
                         Ok(o) => {
                             match &o {
                                 ReadMessage::Do(tlv) => {
-                                    if let Some(display_message) =
-                                        tlv.get_val::<StringAsciiTagValue>("FF01 / DF46").unwrap()
+                                    if let Some(preference) = Self::language_preference(tlv) {
+                                        events.publish(DeviceEvent::LanguagePreference(preference));
+                                    }
+                                    if let Ok(Some(display_message)) =
+                                        tlv.get_val::<Vec<u8>>("FF01 / DF46")
                                     {
-                                        if let Some(handler) =
-                                            &notify_callbacks.lock().unwrap().external_display
-                                        {
-                                            handler(&display_message)
-                                        }
+                                        events.publish(DeviceEvent::DisplayMessage(
+                                            Self::display_message(
+                                                *display_mode.lock().unwrap(),
+                                                &display_message,
+                                            ),
+                                        ));
                                         continue;
                                     }
-                                    if let Some(internal_log) =
-                                        tlv.get_val::<StringAsciiTagValue>("FF01 / DF8154").unwrap()
+                                    if let Ok(Some(internal_log)) =
+                                        tlv.get_val::<StringAsciiTagValue>("FF01 / DF8154")
                                     {
-                                        if let Some(handler) =
-                                            &notify_callbacks.lock().unwrap().internal_log
-                                        {
-                                            handler(&internal_log)
-                                        }
+                                        events.publish(DeviceEvent::InternalLog(
+                                            internal_log.to_owned(),
+                                        ));
                                         continue;
                                     }
                                     if let Some(_) = tlv.find_val("FF01 / DF08") {
-                                        if let Some(handler) =
-                                            &notify_callbacks.lock().unwrap().card_removal
-                                        {
-                                            handler()
-                                        }
+                                        events.publish(DeviceEvent::CardRemoved);
                                         continue;
                                     }
                                 }
                                 _ => {}
                             }
 
-                            match read_out.send(Ok(o)) {
+                            match read_out.send(ReadOut::Message(Ok(o))) {
                                 Ok(_) => {}
                                 Err(_) => {
                                     log::debug!("read_out receiver is disconnected");
@@ -129,7 +172,9 @@ impl Uno8NfcDevice {
                         Err(e) => match e {
                             TryReadMessageError::Empty => {}
                             TryReadMessageError::Other(m) => {
-                                match read_out.send(Err(ReadMessageError::Other(m))) {
+                                match read_out
+                                    .send(ReadOut::Message(Err(ReadMessageError::Other(m))))
+                                {
                                     Ok(_) => {}
                                     Err(_) => {
                                         log::debug!("read_out receiver is disconnected");
@@ -175,30 +220,25 @@ impl Uno8NfcDevice {
     pub fn get_ack_timeout(&self) -> Duration {
         self.ask_timeout
     }
-
-    pub fn set_external_display(&mut self, f: impl Fn(&String) + Send + 'static) {
-        self.notify_callbacks.lock().unwrap().external_display = Some(Box::new(f));
-    }
-
-    pub fn set_internal_log(&mut self, f: impl Fn(&String) + Send + 'static) {
-        self.notify_callbacks.lock().unwrap().internal_log = Some(Box::new(f));
-    }
-
-    pub fn set_card_removal(&mut self, f: impl Fn() + Send + 'static) {
-        self.notify_callbacks.lock().unwrap().card_removal = Some(Box::new(f));
-    }
 }
 
 impl Uno8NfcDevice {
-    fn write_do(&self, tlv: Tlv) -> Result<(), DeviceError> {
+    fn check_config_scope(scope: &ConfigScope) -> Result<(), DeviceError> {
+        match scope {
+            ConfigScope::Global => Ok(()),
+            ConfigScope::Aid(_) | ConfigScope::Kernel(_) => Err(DeviceError::NotSupported),
+        }
+    }
+
+    pub(crate) fn write_do(&self, tlv: Tlv) -> Result<(), DeviceError> {
         self.write(WriteMessage::Do(tlv))
     }
 
-    fn write_get(&self, tlv: Tlv) -> Result<(), DeviceError> {
+    pub(crate) fn write_get(&self, tlv: Tlv) -> Result<(), DeviceError> {
         self.write(WriteMessage::Get(tlv))
     }
 
-    fn write_set(&self, tlv: Tlv) -> Result<(), DeviceError> {
+    pub(crate) fn write_set(&self, tlv: Tlv) -> Result<(), DeviceError> {
         self.write(WriteMessage::Set(tlv))
     }
 
@@ -221,17 +261,7 @@ impl Uno8NfcDevice {
             },
         }
 
-        let message = (match self.read_out.recv_timeout(self.ask_timeout) {
-            Ok(o) => o,
-            Err(e) => match e {
-                mpsc::RecvTimeoutError::Timeout => {
-                    Err(DeviceError::Timeout("recieved read ACK".into()))?
-                }
-                mpsc::RecvTimeoutError::Disconnected => Err(DeviceError::MessageChannel(
-                    "channel is disconnected".into(),
-                ))?,
-            },
-        })?;
+        let message = self.recv_timeout(self.ask_timeout, "recieved read ACK")?;
 
         match message {
             ReadMessage::Ask => Ok(()),
@@ -248,7 +278,7 @@ impl Uno8NfcDevice {
         }
     }
 
-    fn read_success(&self) -> Result<Tlv, DeviceError> {
+    pub(crate) fn read_success(&self) -> Result<Tlv, DeviceError> {
         let tlv = self.read()?;
         match tlv.tag() {
             0xFF01 => Ok(tlv),
@@ -258,18 +288,8 @@ impl Uno8NfcDevice {
         }
     }
 
-    fn read(&self) -> Result<Tlv, DeviceError> {
-        let message = (match self.read_out.recv_timeout(self.read_timeout) {
-            Ok(o) => o,
-            Err(e) => match e {
-                mpsc::RecvTimeoutError::Timeout => {
-                    Err(DeviceError::Timeout("recieved read".into()))?
-                }
-                mpsc::RecvTimeoutError::Disconnected => Err(DeviceError::MessageChannel(
-                    "channel is disconnected".into(),
-                ))?,
-            },
-        })?;
+    pub(crate) fn read(&self) -> Result<Tlv, DeviceError> {
+        let message = self.recv_timeout(self.read_timeout, "recieved read")?;
 
         let tlv = match message {
             ReadMessage::Ask => {
@@ -289,24 +309,36 @@ impl Uno8NfcDevice {
         return Ok(tlv);
     }
 
-    fn read_ct(&self, cancel_flag: Arc<AtomicBool>) -> Result<Tlv, DeviceError> {
+    pub(crate) fn read_ct(&self, cancel: &CancellationToken) -> Result<Tlv, DeviceError> {
+        let read_wake = self.read_wake.clone();
+        let _registration = cancel.on_cancel(move |_| {
+            if let Some(read_wake) = read_wake.upgrade() {
+                let _ = read_wake.send(ReadOut::Wake);
+            }
+        });
+
         loop {
-            if cancel_flag.load(Ordering::SeqCst) {
+            if cancel.is_cancelled() {
                 Err(DeviceError::OperationCanceled)?
             }
 
-            let message = (match self.read_out.try_recv() {
-                Ok(o) => o,
-                Err(e) => match e {
-                    TryRecvError::Empty => {
-                        thread::sleep(Duration::from_millis(1));
-                        continue;
-                    }
-                    TryRecvError::Disconnected => Err(DeviceError::MessageChannel(
-                        "channel is disconnected".into(),
-                    ))?,
+            let read_out = match cancel.remaining() {
+                Some(remaining) => match self.read_out.recv_timeout(remaining) {
+                    Ok(o) => o,
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => Err(
+                        DeviceError::MessageChannel("channel is disconnected".into()),
+                    )?,
                 },
-            })?;
+                None => self.read_out.recv().map_err(|_| {
+                    DeviceError::MessageChannel("channel is disconnected".into())
+                })?,
+            };
+
+            let message = match read_out {
+                ReadOut::Message(o) => o?,
+                ReadOut::Wake => continue,
+            };
 
             let tlv = match message {
                 ReadMessage::Ask => Err(DeviceError::Other(
@@ -322,6 +354,24 @@ impl Uno8NfcDevice {
             return Ok(tlv);
         }
     }
+
+    /// Receives next message, skipping wake ups left by cancelled reads
+    fn recv_timeout(&self, timeout: Duration, operation: &str) -> Result<ReadMessage, DeviceError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.read_out.recv_timeout(timeout) {
+                Ok(ReadOut::Message(o)) => return Ok(o?),
+                Ok(ReadOut::Wake) => continue,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    Err(DeviceError::Timeout(operation.into()))?
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => Err(DeviceError::MessageChannel(
+                    "channel is disconnected".into(),
+                ))?,
+            }
+        }
+    }
 }
 
 impl Uno8NfcDevice {
@@ -332,10 +382,161 @@ impl Uno8NfcDevice {
     }
 
     fn set_poll_timeout(&self, value: u16) -> Result<(), DeviceError> {
-        self.write_do(Tlv::new_spec(0xDF8212, U16BigEndianTagValue::new(value))?)?;
+        self.write_do(Tlv::new_spec(0xDF8212, value)?)?;
         self.read_success()?;
         Ok(())
     }
+
+    /// Builds outcome from the kernel data of the successful poll response
+    fn kernel_outcome(tlv: Tlv) -> Result<PollOutcome, DeviceError> {
+        let kind = match tlv.get_val::<u8>("FF01 / FC / 9F27")? {
+            Some(cid) => OutcomeKind::from_cid(cid),
+            None => {
+                return Err(DeviceError::TlvContent(
+                    "expected cryptogram information data tag".into(),
+                    tlv,
+                ))
+            }
+        };
+        let cvm = match tlv.get_val::<Vec<u8>>("FF01 / FC / 9F34")? {
+            Some(cvm_results) => Cvm::from_cvm_results(&cvm_results),
+            None => Cvm::NotApplicable,
+        };
+        let message = match kind {
+            OutcomeKind::Approved => Some("Approved"),
+            OutcomeKind::Declined => Some("Not authorised"),
+            OutcomeKind::OnlineRequest => Some("Authorising, please wait"),
+            _ => None,
+        };
+
+        Ok(PollOutcome {
+            kind,
+            cvm,
+            receipt: cvm.signature_required(),
+            ui_request: message.map(|x| UiRequest {
+                message: x.to_owned(),
+                annex_e: None,
+            }),
+            tlv,
+        })
+    }
+
+    /// Builds `FD` template starting the EMV macro
+    fn transaction_template(purchase: Option<&PollEmvPurchase>) -> Result<Tlv, DeviceError> {
+        let s = match purchase {
+            Some(s) => s,
+            None => return Ok(Tlv::new(0xFD, Value::Nothing)?),
+        };
+
+        let mut tags = vec![
+            Tlv::new_spec(0x9C, TransactionTypeTagValue::new(s.transaction_type))?,
+            Tlv::new_spec(0x5F2A, IntTagValue::new((s.currency_code as u64, 4)))?,
+            Tlv::new_spec(0x9F02, IntTagValue::new((s.amount, 12)))?,
+        ];
+        if let Some(amount_other) = s.amount_other {
+            tags.push(Tlv::new_spec(0x9F03, IntTagValue::new((amount_other, 12)))?);
+        }
+        Ok(Tlv::new(0xFD, Value::TlvList(tags))?)
+    }
+
+    /// Decodes `DF46` sent in `ExtDisplayMode::Simple` as an index of `SIMPLE_MESSAGES`
+    /// and in `ExtDisplayMode::Full` as English text
+    ///
+    /// While the mode is unknown a single byte is taken for an index.
+    fn display_message(mode: Option<ExtDisplayMode>, raw: &[u8]) -> DisplayMessage {
+        match (mode, raw) {
+            (Some(ExtDisplayMode::Full), _) => {
+                DisplayMessage::from_text(&String::from_utf8_lossy(raw))
+            }
+            (_, [index]) => SIMPLE_MESSAGES
+                .iter()
+                .find(|(x, _)| x == index)
+                .map_or(DisplayMessage::Unknown(*index), |(_, message)| {
+                    message.clone()
+                }),
+            _ => DisplayMessage::from_text(&String::from_utf8_lossy(raw)),
+        }
+    }
+
+    /// Language Preference (5F2D) sent alone or in the kernel data `FC`
+    fn language_preference(tlv: &Tlv) -> Option<Vec<u8>> {
+        ["FF01 / 5F2D", "FF01 / FC / 5F2D"]
+            .iter()
+            .find_map(|path| tlv.get_val::<Vec<u8>>(path).ok().flatten())
+    }
+
+    /// Waits for the response of a macro like `FD`, stopping the macro on cancellation
+    ///
+    /// Returns `None` if the macro was terminated by the stop instruction.
+    pub(crate) fn read_macro(
+        &self,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Tlv>, DeviceError> {
+        let mut current_ct = cancel.clone();
+        let mut stopping = false;
+        loop {
+            match self.read_ct(&current_ct) {
+                Ok(tlv) => {
+                    if let Some(terminate) = tlv.get_val::<AnnexETagValue>("FF03 / F2 / DF68")? {
+                        if *terminate == AnnexE::EmvTransactionTerminated {
+                            return Ok(None);
+                        }
+                    }
+                    if tlv.tag() == 0xFF02 {
+                        return Err(DeviceError::NotSupported);
+                    }
+                    return Ok(Some(tlv));
+                }
+                Err(e) => match e {
+                    DeviceError::OperationCanceled if !stopping => {
+                        self.stop_macro()?;
+                        stopping = true;
+                        current_ct = CancellationToken::with_timeout(self.read_timeout);
+                    }
+                    DeviceError::OperationCanceled => {
+                        Err(DeviceError::Timeout("recieved macro stop".into()))?
+                    }
+                    _ => Err(e)?,
+                },
+            };
+        }
+    }
+
+    /// Waits for the result of the transaction started by `FD` instruction
+    ///
+    /// The reader keeps polling after a named `AnnexE` code, the macro is stopped
+    /// before such an outcome is returned.
+    fn read_outcome(&self, cancel: &CancellationToken) -> Result<PollEmvResult, DeviceError> {
+        let tlv = match self.read_macro(cancel)? {
+            Some(tlv) => tlv,
+            None => return Ok(PollEmvResult::Canceled),
+        };
+
+        if let Some(code) = tlv.get_val::<AnnexETagValue>("FF03 / F2 / DF68")? {
+            if let AnnexE::Unknown(_) = *code {
+                return Ok(PollEmvResult::Outcome(PollOutcome::from_annex_e(
+                    *code, tlv,
+                )));
+            }
+
+            let stop = CancellationToken::new();
+            stop.cancel();
+            return match self.read_macro(&stop)? {
+                // the card was processed before the macro got the stop instruction
+                Some(kernel) if kernel.find_val("FF01 / FC").is_some() => {
+                    Ok(PollEmvResult::Outcome(Self::kernel_outcome(kernel)?))
+                }
+                _ => Ok(PollEmvResult::Outcome(PollOutcome::from_annex_e(
+                    *code, tlv,
+                ))),
+            };
+        }
+        if tlv.find_val("FF01 / FC").is_some() {
+            return Ok(PollEmvResult::Outcome(Self::kernel_outcome(tlv)?));
+        }
+
+        Err(DeviceError::TlvContent("invalid response TLV".into(), tlv))
+    }
 }
 
 impl CardLessDevice for Uno8NfcDevice {
@@ -359,54 +560,17 @@ impl CardLessDevice for Uno8NfcDevice {
     fn poll_emv(
         &mut self,
         purchase: Option<PollEmvPurchase>,
-        cancel_flag: Arc<AtomicBool>,
+        cancel: &CancellationToken,
     ) -> Result<PollEmvResult, DeviceError> {
-        self.set_poll_timeout(0)?;
-
-        let r_tlv = match purchase {
-            Some(s) => Tlv::new(
-                0xFD,
-                Value::TlvList(vec![
-                    Tlv::new(0x9C, Value::Val(vec![s.p_type]))?,
-                    Tlv::new_spec(0x5F2A, IntTagValue::new((s.currency_code as u64, 4)))?,
-                    Tlv::new_spec(0x9F02, IntTagValue::new((s.amount, 12)))?,
-                ]),
-            )?,
-            None => Tlv::new(0xFD, Value::Nothing)?,
-        };
-
-        self.write_do(r_tlv)?;
-
-        let dummy_ct = Arc::new(AtomicBool::new(false));
+        // Display messages of the transaction are decoded by the mode
+        if self.display_mode.lock().unwrap().is_none() {
+            self.get_display_mode()?;
+        }
 
-        let mut current_ct = cancel_flag;
-        loop {
-            match self.read_ct(current_ct.clone()) {
-                Ok(tlv) => {
-                    if let Some(terminate) = tlv.get_val::<AnnexETagValue>("FF03 / F2 / DF68")? {
-                        match *terminate {
-                            AnnexE::EmvTransactionTerminated => return Ok(PollEmvResult::Canceled),
-                            AnnexE::CollisionMoreThanOnePICCDetected => continue,
-                            AnnexE::EmvTransactionTerminatedSeePhone => continue,
-                            AnnexE::EmvTransactionTerminatedUseContactChannel => continue,
-                            AnnexE::EmvTransactionTerminatedTryAgain => continue,
-                        }
-                    }
-                    if let Some(_) = tlv.find_val("FF01 / FC") {
-                        return Ok(PollEmvResult::Success(tlv));
-                    }
+        self.set_poll_timeout(0)?;
+        self.write_do(Self::transaction_template(purchase.as_ref())?)?;
 
-                    Err(DeviceError::TlvContent("invalid response TLV".into(), tlv))?;
-                }
-                Err(e) => match e {
-                    DeviceError::OperationCanceled => {
-                        self.stop_macro()?;
-                        current_ct = dummy_ct.clone()
-                    }
-                    _ => Err(e)?,
-                },
-            };
-        }
+        self.read_outcome(cancel)
     }
 
     fn ext_display(&mut self) -> Option<& dyn ExtDisplay> {
@@ -416,6 +580,14 @@ impl CardLessDevice for Uno8NfcDevice {
     fn storage(&mut self) -> Option<&dyn Storage> {
         None
     }
+
+    fn configuration(&mut self) -> Option<&dyn Configuration> {
+        Some(self)
+    }
+
+    fn subscribe(&self) -> EventSubscription {
+        self.events.subscribe()
+    }
 }
 
 impl ExtDisplay for Uno8NfcDevice {
@@ -424,7 +596,10 @@ impl ExtDisplay for Uno8NfcDevice {
 
         let tlv = self.read_success()?;
         match tlv.get_val::<ExtDisplayModeTagValue>("FF01 / DF46")? {
-            Some(s) => Ok(*s),
+            Some(s) => {
+                *self.display_mode.lock().unwrap() = Some(*s);
+                Ok(*s)
+            }
             None => Err(DeviceError::TlvContent(
                 "expected external display mode tag".into(),
                 tlv,
@@ -435,6 +610,279 @@ impl ExtDisplay for Uno8NfcDevice {
     fn set_display_mode(&self, value: &ExtDisplayMode) -> Result<(), DeviceError> {
         self.write_set(Tlv::new_spec(0xDF46, ExtDisplayModeTagValue::new(*value))?)?;
         self.read()?;
+        *self.display_mode.lock().unwrap() = Some(*value);
+        Ok(())
+    }
+}
+
+/// Parameters of `TAG_DICTIONARY` are read and written one by one with Get and Set instructions.
+/// Only `ConfigScope::Global` is supported, the protocol has no template for AID or kernel
+/// parameters.
+impl Configuration for Uno8NfcDevice {
+    /// Parameters the reader answers with `FF02` template are left out
+    fn read_config(&self, scope: &ConfigScope) -> Result<TerminalConfig, DeviceError> {
+        Self::check_config_scope(scope)?;
+
+        let mut config = TerminalConfig::new();
+        for spec in TAG_DICTIONARY {
+            self.write_get(Tlv::new(spec.tag, Value::Nothing)?)?;
+            let tlv = match self.read_success() {
+                Ok(tlv) => tlv,
+                Err(DeviceError::TlvContent(_, tlv)) if tlv.tag() == 0xFF02 => continue,
+                Err(e) => return Err(e),
+            };
+            match tlv.get_val::<Vec<u8>>(&format!("FF01 / {:X}", spec.tag))? {
+                Some(value) => config = config.with_param(spec.tag, value),
+                None => {
+                    return Err(DeviceError::TlvContent(
+                        format!("expected {} tag", spec.name),
+                        tlv,
+                    ))
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    fn write_config(
+        &self,
+        scope: &ConfigScope,
+        config: &TerminalConfig,
+    ) -> Result<(), DeviceError> {
+        Self::check_config_scope(scope)?;
+        config.validate()?;
+
+        for (tag, value) in config.params() {
+            self.write_set(Tlv::new(tag, Value::Val(value.to_vec()))?)?;
+            self.read_success()?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::VecDeque;
+
+    /// Answers every written message with the next scripted replies
+    /// and records its tag, then panics like a reader that is unplugged
+    struct FakeChannel {
+        replies: Mutex<VecDeque<Vec<ReadMessage>>>,
+        reads: Mutex<VecDeque<ReadMessage>>,
+        writes: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl FakeChannel {
+        fn new(replies: Vec<Vec<ReadMessage>>) -> Self {
+            Self {
+                replies: Mutex::new(replies.into()),
+                reads: Mutex::new(VecDeque::new()),
+                writes: Arc::new(Mutex::new(vec![])),
+            }
+        }
+    }
+
+    impl MessageChannel for FakeChannel {
+        fn write(&self, message: &WriteMessage) -> Result<(), WriteMessageError> {
+            let tlv = match message {
+                WriteMessage::Do(tlv) | WriteMessage::Get(tlv) | WriteMessage::Set(tlv) => tlv,
+            };
+            self.writes.lock().unwrap().push(tlv.tag());
+
+            if let Some(replies) = self.replies.lock().unwrap().pop_front() {
+                self.reads.lock().unwrap().extend(replies);
+            }
+            Ok(())
+        }
+
+        fn try_read(&self) -> Result<ReadMessage, TryReadMessageError> {
+            if let Some(message) = self.reads.lock().unwrap().pop_front() {
+                return Ok(message);
+            }
+            if self.replies.lock().unwrap().is_empty() {
+                panic!("reader is unplugged");
+            }
+            Err(TryReadMessageError::Empty)
+        }
+    }
+
+    fn success(tags: Vec<Tlv>) -> Tlv {
+        Tlv::new(0xFF01, Value::TlvList(tags)).unwrap()
+    }
+
+    fn annex_e(code: u8) -> Tlv {
+        Tlv::new(
+            0xFF03,
+            Value::TlvList(vec![Tlv::new(
+                0xF2,
+                Value::TlvList(vec![Tlv::new(0xDF68, Value::Val(vec![code])).unwrap()]),
+            )
+            .unwrap()]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn read_ct_disconnected_test() {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let device = Uno8NfcDevice::new(FakeChannel::new(vec![]));
+            let _ = tx.send(device.read_ct(&CancellationToken::new()));
+        });
+
+        match rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(Err(DeviceError::MessageChannel(_))) => {}
+            Ok(o) => panic!("unexpected result {:?}", o),
+            Err(_) => panic!("read_ct is not woken up by the disconnected channel"),
+        }
+    }
+
+    #[test]
+    fn read_outcome_stops_macro_test() {
+        let display_mode = Tlv::new(0xDF46, Value::Val(vec![0x01])).unwrap();
+        let channel = FakeChannel::new(vec![
+            vec![
+                ReadMessage::Ask,
+                ReadMessage::Get(success(vec![display_mode])),
+            ],
+            vec![ReadMessage::Ask, ReadMessage::Do(success(vec![]))],
+            vec![ReadMessage::Ask, ReadMessage::Do(annex_e(0x2B))],
+            vec![
+                ReadMessage::Ask,
+                ReadMessage::Do(success(vec![])),
+                ReadMessage::Do(annex_e(0x09)),
+            ],
+        ]);
+        let writes = channel.writes.clone();
+        let mut device = Uno8NfcDevice::new(channel);
+
+        match device.poll_emv(None, &CancellationToken::new()).unwrap() {
+            PollEmvResult::Outcome(outcome) => assert_eq!(outcome.kind, OutcomeKind::TryAgain),
+            PollEmvResult::Canceled => panic!("poll is not expected to be canceled"),
+        }
+        assert_eq!(
+            *writes.lock().unwrap(),
+            vec![0xDF46, 0xDF8212, 0xFD, 0xDF7D]
+        );
+    }
+
+    #[test]
+    fn configuration_test() {
+        let country_code = Tlv::new(0x9F1A, Value::Val(vec![0x06, 0x43])).unwrap();
+        let mut replies = vec![vec![
+            ReadMessage::Ask,
+            ReadMessage::Get(success(vec![country_code])),
+        ]];
+        for _ in 1..TAG_DICTIONARY.len() {
+            let unsupported = Tlv::new(0xFF02, Value::TlvList(vec![])).unwrap();
+            replies.push(vec![ReadMessage::Ask, ReadMessage::Get(unsupported)]);
+        }
+        for _ in 0..2 {
+            replies.push(vec![ReadMessage::Ask, ReadMessage::Set(success(vec![]))]);
+        }
+        let channel = FakeChannel::new(replies);
+        let writes = channel.writes.clone();
+        let mut device = Uno8NfcDevice::new(channel);
+        let configuration = device.configuration().unwrap();
+
+        let config = configuration.read_config(&ConfigScope::Global).unwrap();
+        assert_eq!(config, TerminalConfig::new().with_country_code(643));
+
+        let config = TerminalConfig::new()
+            .with_transaction_limit(1_000_000)
+            .with_terminal_id("TID00001");
+        configuration
+            .write_config(&ConfigScope::Global, &config)
+            .unwrap();
+        assert_eq!(
+            writes.lock().unwrap()[TAG_DICTIONARY.len()..],
+            [0x9F1C, 0xDF8124]
+        );
+
+        assert!(matches!(
+            configuration.read_config(&ConfigScope::Kernel(0x02)),
+            Err(DeviceError::NotSupported)
+        ));
+        assert!(matches!(
+            configuration.write_config(&ConfigScope::Global, &config.with_param(0x9F02, vec![])),
+            Err(DeviceError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn kernel_outcome_test() {
+        let tlv = Tlv::from_vec(&[
+            0xFF, 0x01, 0x0C, 0xFC, 0x0A, 0x9F, 0x27, 0x01, 0x80, 0x9F, 0x34, 0x03, 0x03, 0x00,
+            0x02,
+        ])
+        .unwrap();
+        let outcome = Uno8NfcDevice::kernel_outcome(tlv).unwrap();
+        assert_eq!(outcome.kind, OutcomeKind::OnlineRequest);
+        assert_eq!(outcome.cvm, Cvm::OfflinePinAndSignature);
+        assert!(outcome.receipt);
+
+        let tlv = Tlv::from_vec(&[
+            0xFF, 0x01, 0x08, 0xFC, 0x06, 0x9F, 0x34, 0x03, 0x1F, 0x00, 0x02,
+        ])
+        .unwrap();
+        assert!(matches!(
+            Uno8NfcDevice::kernel_outcome(tlv),
+            Err(DeviceError::TlvContent(_, _))
+        ));
+    }
+
+    #[test]
+    fn display_message_test() {
+        assert_eq!(
+            Uno8NfcDevice::display_message(Some(ExtDisplayMode::Simple), &[0x15]),
+            DisplayMessage::PresentCard
+        );
+        assert_eq!(
+            Uno8NfcDevice::display_message(Some(ExtDisplayMode::Full), b"Present Card"),
+            DisplayMessage::PresentCard
+        );
+        assert_eq!(
+            Uno8NfcDevice::display_message(Some(ExtDisplayMode::Full), b"5"),
+            DisplayMessage::Text("5".into())
+        );
+        assert_eq!(
+            Uno8NfcDevice::display_message(None, &[0x10]),
+            DisplayMessage::RemoveCard
+        );
+        assert_eq!(
+            Uno8NfcDevice::display_message(Some(ExtDisplayMode::Simple), &[0x30]),
+            DisplayMessage::Unknown(0x30)
+        );
+        for (index, message) in SIMPLE_MESSAGES {
+            assert_eq!(message.id(), Some(*index));
+        }
+    }
+
+    #[test]
+    fn language_preference_test() {
+        let kernel = Tlv::new(
+            0xFC,
+            Value::TlvList(vec![Tlv::new(0x5F2D, Value::Val(b"ruen".to_vec())).unwrap()]),
+        )
+        .unwrap();
+        let channel = FakeChannel::new(vec![vec![
+            ReadMessage::Ask,
+            ReadMessage::Do(success(vec![kernel])),
+        ]]);
+        let device = Uno8NfcDevice::new(channel);
+        let events = device.subscribe();
+
+        device
+            .write_do(Tlv::new(0xFD, Value::Nothing).unwrap())
+            .unwrap();
+        match events.next_timeout(Duration::from_secs(5)) {
+            Some(record) => assert_eq!(
+                record.event,
+                DeviceEvent::LanguagePreference(b"ruen".to_vec())
+            ),
+            None => panic!("language preference is not published"),
+        }
+    }
+}
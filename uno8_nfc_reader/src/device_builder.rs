@@ -2,6 +2,7 @@ use crate::device;
 
 use std::time::Duration;
 
+use card_less_reader::async_device::AsyncDevice;
 use hidapi::{HidApi, HidError};
 
 use device::Uno8NfcDevice;
@@ -29,22 +30,16 @@ impl Uno8NfcDeviceBuilder {
         self
     }
 
-    pub fn set_external_display(mut self, f: impl Fn(&String) + Send + 'static) -> Self {
-        self.device.set_external_display(f);
-        self
-    }
-
-    pub fn set_internal_log(mut self, f: impl Fn(&String) + Send + 'static) -> Self {
-        self.device.set_internal_log(f);
-        self
+    pub fn finish(self) -> Uno8NfcDevice {
+        self.device
     }
 
-    pub fn set_card_removal(mut self, f: impl Fn() + Send + 'static) -> Self {
-        self.device.set_card_removal(f);
-        self
+    pub fn finish_async(self) -> AsyncDevice<Uno8NfcDevice> {
+        AsyncDevice::new(self.device)
     }
 
-    pub fn finish(self) -> Uno8NfcDevice {
-        self.device
+    #[cfg(feature = "tokio")]
+    pub fn finish_tokio(self) -> AsyncDevice<Uno8NfcDevice> {
+        AsyncDevice::with_tokio(self.device)
     }
 }
@@ -169,8 +169,8 @@ fn calculate_length_field(byte_size: usize) -> Vec<u8> {
     } else if byte_size + 2 <= 0xFF {
         vec![0x81, (byte_size + 2) as u8]
     } else if byte_size + 3 <= 0xFFFF {
-        let mut vec: Vec<u8> = vec![0x82];
-        BigEndian::write_u16(&mut vec, byte_size as u16);
+        let mut vec: Vec<u8> = vec![0x82, 0x00, 0x00];
+        BigEndian::write_u16(&mut vec[1..], (byte_size + 3) as u16);
         vec
     } else {
         panic!("incorrect message size");
@@ -233,3 +233,28 @@ impl From<TlvError> for TryReadMessageError {
         TryReadMessageError::Other(format!("{}", error))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_field_test() {
+        for &size in &[5, 0x7E, 0x7F, 0xFD, 0xFE, 0xFF, 0x100, 0x12C, 0xFFFC] {
+            let field = calculate_length_field(size);
+
+            let mut frame = vec![0x02];
+            frame.extend_from_slice(&field);
+            frame.resize(size + field.len(), 0x00);
+
+            assert_eq!(
+                get_message_length(&frame, 1),
+                Some((frame.len() as u16, 1 + field.len())),
+                "size {}",
+                size
+            );
+        }
+
+        assert_eq!(calculate_length_field(0x100), vec![0x82, 0x01, 0x03]);
+    }
+}
@@ -40,6 +40,13 @@ impl TagValue for SerialNumberTagValue {
     where
         Self: Sized,
     {
+        if raw.len() != 8 {
+            return Err(TlvError::ParseTagValue(format!(
+                "expected 8 bytes, found {}",
+                raw.len()
+            )));
+        }
+
         Ok(Self {
             bom_version: U16BigEndianTagValue::from_raw(&raw[0..2])?,
             partial_pn: U16BigEndianTagValue::from_raw(&raw[2..4])?,